@@ -2,6 +2,14 @@ use std::mem::discriminant;
 use crate::libkagami::complex::overrides::ASSOverride;
 
 pub fn already_active(current: &[ASSOverride], candidate: &ASSOverride) -> bool {
+    // karaoke tags time the syllable after them, a repeated one is never a no-op
+    let karaoke = matches!(
+        candidate,
+        ASSOverride::K(_) | ASSOverride::Kf(_) | ASSOverride::Ko(_) | ASSOverride::KSweep(_)
+    );
+    if karaoke {
+        return false;
+    }
     current
         .iter()
         .any(|c| discriminant(c) == discriminant(candidate) && c == candidate)
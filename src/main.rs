@@ -7,9 +7,22 @@ mod libkagami;
 use crate::modules::audio::AudioReportAction;
 use crate::modules::{
     audio::{play_audio, AudioCommand},
+    config::{Config, USAGE},
     crystal_manager::crystal_manager,
 };
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let (config, problems) = match Config::load(&args) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("neocrystal: {}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
     // establish communications and threads, then give the job to crystal_manager fn
     let (tx, rx): (Sender<AudioCommand>, Receiver<AudioCommand>) = mpsc::channel();
     let (tx_proc, rx_proc): (Sender<AudioReportAction>, Receiver<AudioReportAction>) = mpsc::channel();
@@ -18,7 +31,10 @@ fn main() {
         Err(_) => (),
     });
 
-    tx.send(AudioCommand::SetVolume(0.5)).unwrap();
+    tx.send(AudioCommand::SetVolume(config.volume as f32 / 100.0))
+        .unwrap();
+    tx.send(AudioCommand::SetCrossfade(config.crossfade))
+        .unwrap();
 
-    crystal_manager(tx, rx_proc);
+    crystal_manager(tx, rx_proc, config, problems);
 }
@@ -1,8 +1,10 @@
 use rodio::*;
 use std::io::BufReader;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use symphonia::core::{
     formats::FormatOptions,
     io::MediaSourceStream,
@@ -11,37 +13,46 @@ use symphonia::core::{
 };
 use std::fs::File;
 
+/// Length from the container's frame count, zero when the file can't be probed.
 pub fn audio_duration(path: &str) -> Duration {
-    let file = File::open(path).ok().unwrap();
+    probe_duration(path).unwrap_or_default()
+}
+
+fn probe_duration(path: &str) -> Option<Duration> {
+    let file = File::open(path).ok()?;
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
     let mut hint = Hint::new();
-    if let Some(ext) = path.split('.').last() {
+    if let Some(ext) = path.rsplit('.').next() {
         hint.with_extension(ext);
     }
 
     let probed = symphonia::default::get_probe()
         .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
-        .ok().unwrap();
+        .ok()?;
 
     let format = probed.format;
-    let track = format.default_track().unwrap();
+    let track = format.default_track()?;
 
-    let sample_rate = track.codec_params.sample_rate.unwrap();
-    let frames = track.codec_params.n_frames.unwrap();
+    let sample_rate = track.codec_params.sample_rate?;
+    let frames = track.codec_params.n_frames?;
 
-    Duration::from_secs_f64(frames as f64 / sample_rate as f64)
+    Some(Duration::from_secs_f64(frames as f64 / sample_rate as f64))
 }
 
-
-
 pub enum AudioCommand {
-    Play(String),
+    Play(String, f32), // path and linear ReplayGain factor
+    // track to append behind the current one, None forgets it.
+    // bool says whether it may be crossfaded into (manager turns it off for loop and forced tracks)
+    Preload(Option<(String, f32)>, bool),
+    SetCrossfade(Duration),
+    SetGain(f32), // changes the gain of the track that's playing right now
     SetVolume(f32),
     Pause,
     Resume,
-    SeekForward,
-    SeekBackward,
+    SeekForward(Duration),
+    SeekBackward(Duration),
+    SeekTo(Duration),
     Stop,
 }
 
@@ -49,29 +60,117 @@ pub enum AudioReportAction {
     EOF,
     Pause,
     Duration(String, Duration),
+    Advanced(String), // moved into the preloaded track, either gapless or by starting a crossfade
 }
 
+/// How close to the end of the current track the preloaded one gets appended.
+/// Has to be comfortably bigger than the 200ms command poll below.
+const PRELOAD_WINDOW: Duration = Duration::from_secs(2);
+/// Poll interval while a crossfade is running, volume ramps are stepped at this rate.
+const FADE_TICK: Duration = Duration::from_millis(20);
+
 struct Orchestrator { r: bool }
 impl Orchestrator { 
     pub fn res(&mut self) { self.r = false; } 
     pub fn act(&mut self) -> bool { if self.r { return false } else { self.r = true; return true } }
 }
 
+/// Gain of a single source. Sources keep a clone and poll it,
+/// so it can still change after they went into a sink.
+#[derive(Clone)]
+struct Gain(Arc<AtomicU32>);
+impl Gain {
+    fn new(value: f32) -> Self {
+        Self(Arc::new(AtomicU32::new(value.to_bits())))
+    }
+    fn set(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+    fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+fn open_track(path: &str, gain: &Gain) -> Result<impl Source + Send + 'static, Box<dyn std::error::Error>> {
+    let file: File = File::open(path)?;
+    let source: Decoder<BufReader<File>> = Decoder::new(BufReader::new(file))?;
+    let gain = gain.clone();
+    Ok(source
+        .amplify(gain.get())
+        .periodic_access(Duration::from_millis(50), move |s| s.set_factor(gain.get())))
+}
+
+/// Seeks the playing track, clamped to a second before its end so it doesn't get skipped.
+/// Returns true if the source had to be rebuilt, which also drops anything queued behind it.
+fn seek_to(
+    sink: &Sink,
+    path: &str,
+    duration: Duration,
+    gain: &Gain,
+    target: Duration,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if path == "uinit" {
+        return Ok(false);
+    }
+    let target = if duration != Duration::ZERO && target + Duration::from_secs(1) >= duration {
+        duration.saturating_sub(Duration::from_secs(1))
+    } else {
+        target
+    };
+    if sink.try_seek(target).is_ok() || target >= sink.get_pos() {
+        return Ok(false);
+    }
+    // some decoders refuse to go backwards, a fresh one starts at 0 so it only has to go forward
+    let paused = sink.is_paused();
+    let source = open_track(path, gain)?;
+    sink.clear();
+    sink.append(source);
+    let _ = sink.try_seek(target);
+    if !paused {
+        sink.play();
+    }
+    Ok(true)
+}
+
+/// Drops the outgoing side of a crossfade and puts the incoming one at full volume.
+fn finish_fade(sinks: &[Sink; 2], active: usize, volume: f32) {
+    sinks[1 - active].clear();
+    sinks[active].set_volume(volume);
+}
+
 pub fn play_audio(
     receiver: Receiver<AudioCommand>,
     transmitter: Sender<AudioReportAction>,
 ) -> Result<String, Box<dyn std::error::Error>> {
     let stream_handle: OutputStream =
         rodio::OutputStreamBuilder::open_default_stream().expect("open default audio stream");
-    let sink = rodio::Sink::connect_new(&stream_handle.mixer());
+    // two sinks on the same mixer, the second one only gets used as the other side of a crossfade
+    let sinks = [
+        rodio::Sink::connect_new(stream_handle.mixer()),
+        rodio::Sink::connect_new(stream_handle.mixer()),
+    ];
+    let mut active: usize = 0;
     let mut cached: String = "uinit".to_string();
     let mut orc = Orchestrator { r: false };
     let mut cached_duration: Duration = Duration::ZERO;
     let mut sleep_duration: u8 = 0;
+    // preload: what the manager wants next, queued: what's already sitting in the sink
+    let mut preload: Option<(String, f32)> = None;
+    let mut queued: Option<(String, Duration, Gain)> = None;
+    let mut gain = Gain::new(1.0); // shared with whatever source is playing now
+    let mut volume: f32 = 1.0;
+    let mut crossfade: Duration = Duration::ZERO;
+    let mut fade_allowed: bool = false;
+    let mut fading: Option<Instant> = None;
     loop {
-        if let Ok(ac_command) = receiver.recv_timeout(Duration::from_millis(200)) {
+        let sink = &sinks[active];
+        let poll = if fading.is_some() { FADE_TICK } else { Duration::from_millis(200) };
+        if let Ok(ac_command) = receiver.recv_timeout(poll) {
             match ac_command {
                 AudioCommand::Pause => {
+                    if fading.take().is_some() {
+                        finish_fade(&sinks, active, volume);
+                    }
                     sink.pause();
                     transmitter.send(AudioReportAction::Pause)?; // send a time in the past to indicate paused state
                 }
@@ -84,15 +183,28 @@ pub fn play_audio(
                 }
 
                 AudioCommand::SetVolume(value) => {
-                    sink.set_volume(value);
+                    volume = value;
+                    if fading.is_none() {
+                        sink.set_volume(value);
+                    }
+                }
+                AudioCommand::SetCrossfade(value) => {
+                    crossfade = value;
+                }
+                AudioCommand::SetGain(value) => {
+                    gain.set(value);
                 }
                 AudioCommand::Stop => {
-                    sink.stop();
+                    sinks[0].stop();
+                    sinks[1].stop();
                     break;
                 }
-                AudioCommand::Play(value) => {
-                    let file: File = File::open(value.clone())?;
-                    let source: Decoder<BufReader<File>> = Decoder::new(BufReader::new(file))?;
+                AudioCommand::Play(value, factor) => {
+                    gain = Gain::new(factor);
+                    let source = open_track(&value, &gain)?;
+                    if fading.take().is_some() {
+                        finish_fade(&sinks, active, volume);
+                    }
                     sink.clear();
                     thread::sleep(Duration::from_millis(20));
                     sink.append(source);
@@ -102,35 +214,31 @@ pub fn play_audio(
                     cached = value;
                     sleep_duration = 100;
                     orc.res();
+                    // clear() dropped anything queued, manager resends the preload
+                    preload = None;
+                    queued = None;
                 }
-                AudioCommand::SeekForward => {
-                    if sink.get_pos() + Duration::from_secs(5) >= cached_duration
-                        && cached_duration != Duration::ZERO
-                    {
-                        // If the new position is beyond the end of the track, seek to the end
-                        let _ = sink.try_seek(cached_duration - Duration::from_secs(1));
-                    } else {
-                        match sink.try_seek(sink.get_pos() + Duration::from_secs(5)) {
-                            _ => (),
-                        }
+                AudioCommand::Preload(value, fade) => {
+                    // once appended it's too late to change, sink can't drop a queued source
+                    preload = value;
+                    fade_allowed = fade;
+                }
+                AudioCommand::SeekForward(step) => {
+                    let target = sink.get_pos() + step;
+                    if seek_to(sink, &cached, cached_duration, &gain, target)? {
+                        queued = None; // preload is kept, gets appended again near the end
                     }
                 }
-                AudioCommand::SeekBackward => {
-                    if cached == "uinit".to_string() {
-                        continue;
+                AudioCommand::SeekBackward(step) => {
+                    let target = sink.get_pos().saturating_sub(step);
+                    if seek_to(sink, &cached, cached_duration, &gain, target)? {
+                        queued = None;
                     }
-                    let cachegetpos = sink.get_pos();
-                    let file: File = File::open(cached.clone())?;
-                    let source: Decoder<BufReader<File>> = Decoder::new(BufReader::new(file))?;
-                    sink.clear();
-
-                    sink.append(source);
-                    if cachegetpos <= Duration::from_secs(5) {
-                        ();
-                    } else {
-                        sink.try_seek(cachegetpos - Duration::from_secs(5))?;
+                }
+                AudioCommand::SeekTo(target) => {
+                    if seek_to(sink, &cached, cached_duration, &gain, target)? {
+                        queued = None;
                     }
-                    sink.play();
                 }
             }
         }
@@ -138,6 +246,63 @@ pub fn play_audio(
             continue;
         }
         thread::sleep(Duration::from_millis(sleep_duration as u64));
+        if let Some(started) = fading {
+            let t = (started.elapsed().as_secs_f32() / crossfade.as_secs_f32()).min(1.0);
+            sinks[active].set_volume(volume * t);
+            sinks[1 - active].set_volume(volume * (1.0 - t));
+            if t >= 1.0 {
+                fading = None;
+                finish_fade(&sinks, active, volume);
+            }
+        }
+        if queued.is_some() && sink.len() <= 1 {
+            // previous source is done, sink is already playing the queued one
+            let (path, duration, queued_gain) = queued.take().unwrap();
+            cached = path;
+            cached_duration = duration;
+            gain = queued_gain;
+            preload = None;
+            orc.res();
+            transmitter.send(AudioReportAction::Advanced(cached.clone()))?;
+        }
+        let remaining = duration_autobuild(cached_duration, sink.get_pos());
+        if fade_allowed
+            && fading.is_none()
+            && !crossfade.is_zero()
+            && queued.is_none()
+            && sink.len() == 1
+            && remaining <= crossfade
+            && let Some((path, factor)) = preload.take()
+            && let incoming_gain = Gain::new(factor)
+            && let Ok(source) = open_track(&path, &incoming_gain)
+        {
+            // start the incoming track on the idle sink, the ramps happen on the next ticks.
+            // from here on everything reported is about the incoming track
+            let incoming = &sinks[1 - active];
+            incoming.clear();
+            incoming.set_volume(0.0);
+            incoming.append(source);
+            incoming.play();
+            active = 1 - active;
+            fading = Some(Instant::now());
+            gain = incoming_gain;
+            cached_duration = audio_duration(&path);
+            cached = path;
+            orc.res();
+            transmitter.send(AudioReportAction::Advanced(cached.clone()))?;
+            continue;
+        }
+        if (!fade_allowed || crossfade.is_zero())
+            && queued.is_none()
+            && sink.len() == 1
+            && remaining <= PRELOAD_WINDOW
+            && let Some((path, factor)) = preload.take()
+            && let queued_gain = Gain::new(factor)
+            && let Ok(source) = open_track(&path, &queued_gain)
+        {
+            sink.append(source);
+            queued = Some((path.clone(), audio_duration(&path), queued_gain));
+        }
         if sink.empty() && orc.act() && !sink.is_paused() {
             transmitter.send(AudioReportAction::EOF).unwrap();
         } else if !sink.is_paused() {
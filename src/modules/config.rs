@@ -0,0 +1,730 @@
+// Settings from ~/.config/neocrystal/config.toml, overridden by command line flags.
+//
+//   [library]
+//   roots = ["~/Music", "/mnt/music"]
+//   extensions = ["mp3", "flac"]
+//   exclude = ["*/Podcasts/*"]
+//   follow_symlinks = true
+//   watch = true         # pick up added, changed and removed files while running
+//   playlists = "~/.config/neocrystal/playlists"
+//
+//   [playback]
+//   volume = 50          # 0-100
+//   volume_step = 1
+//   seek_step = 5        # seconds the seek keys jump
+//   crossfade = 0        # seconds of fade between tracks, 0-10, X cycles it
+//   shuffle = "off"      # "bag", "album" or "weighted"
+//   seed = 42            # same seed, same shuffle. Random when not set
+//   repeat = "all"       # "one" or "off"
+//
+//   [ui]
+//   sliding_speed = 300  # milliseconds per character
+//   mouse = true
+//   tag_titles = false   # show title tags instead of file names
+//
+//   [integrations]
+//   rpc = true
+//   mpris = true
+//
+//   [subtitles]
+//   lookup = "sidecar"   # or "off"
+//   dirs = ["subs"]      # searched after the song's own directory, relative to it
+//   formats = ["ass", "lrc"]  # which sidecar wins when a song has several
+//   lines = 3            # rows of the subtitle pane, it takes them from the song list
+//
+// Only the part of TOML these files need is understood: sections, strings, numbers,
+// booleans and lists of strings.
+
+use crate::modules::songs::{RepeatMode, ShuffleMode};
+use crate::modules::subtitle;
+use glob::Pattern;
+use home::home_dir;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+pub struct Entry {
+    pub section: String,
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    let quote = chars.next()?;
+    if quote != '"' && quote != '\'' {
+        return None;
+    }
+    let mut out = String::new();
+    loop {
+        match chars.next()? {
+            '\\' if quote == '"' => match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                c => out.push(c),
+            },
+            c if c == quote => return Some(out),
+            c => out.push(c),
+        }
+    }
+}
+
+fn skip_blank(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+/// Whatever follows the value has to be blank or a comment.
+fn only_comment(chars: &mut std::iter::Peekable<std::str::Chars>) -> bool {
+    skip_blank(chars);
+    chars.peek().is_none_or(|c| *c == '#')
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    let mut chars = text.trim().chars().peekable();
+    let value = match chars.peek()? {
+        '"' | '\'' => Value::Str(parse_string(&mut chars)?),
+        '[' => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                while chars.peek().is_some_and(|c| c.is_whitespace() || *c == ',') {
+                    chars.next();
+                }
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    break;
+                }
+                items.push(parse_string(&mut chars)?);
+            }
+            Value::List(items)
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '#') {
+                word.push(c);
+            }
+            match word.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => Value::Int(word.parse().ok()?),
+            }
+        }
+    };
+    only_comment(&mut chars).then_some(value)
+}
+
+/// Reads `[section]` headers and `key = value` lines. Lines that can't be read are
+/// skipped and described in the second list, prefixed with file and line number.
+pub fn parse_toml(buf: &str, file: &str) -> (Vec<Entry>, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut problems = Vec::new();
+    let mut section = String::new();
+    for (n, line) in buf.lines().enumerate() {
+        let n = n + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix('[') {
+            match rest.split_once(']') {
+                Some((name, tail)) if only_comment(&mut tail.chars().peekable()) => {
+                    section = name.trim().to_string();
+                }
+                _ => problems.push(format!("{}:{}: broken section header", file, n)),
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            problems.push(format!("{}:{}: expected `key = value`", file, n));
+            continue;
+        };
+        let key = key.trim().trim_matches('"').to_string();
+        match parse_value(value) {
+            Some(value) => entries.push(Entry {
+                section: section.clone(),
+                key,
+                value,
+                line: n,
+            }),
+            None => problems.push(format!("{}:{}: can't read the value of `{}`", file, n, key)),
+        }
+    }
+    (entries, problems)
+}
+
+/// "~/Music" -> "/home/user/Music"
+pub fn expand_tilde(path: &str) -> String {
+    match (path.strip_prefix('~'), home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home.display(), rest)
+        }
+        _ => path.to_string(),
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SubtitleLookup {
+    Off,
+    Sidecar,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub roots: Vec<String>,
+    pub extensions: Vec<String>,
+    pub exclude: Vec<Pattern>,
+    pub follow_symlinks: bool,
+    pub watch: bool,
+    /// Directory of the playlist files.
+    pub playlists: String,
+    pub volume: u8,
+    pub volume_step: u8,
+    pub seek_step: Duration,
+    /// Fade between tracks at startup, zero plays them gapless.
+    pub crossfade: Duration,
+    pub shuffle: ShuffleMode,
+    pub seed: Option<u64>,
+    pub repeat: RepeatMode,
+    pub sliding_speed: Duration,
+    pub mouse: bool,
+    pub tag_titles: bool,
+    pub rpc: bool,
+    pub mpris: bool,
+    pub subtitle_lookup: SubtitleLookup,
+    pub subtitle_dirs: Vec<String>,
+    /// Sidecar extensions, the first one a song has is loaded.
+    pub subtitle_formats: Vec<String>,
+    /// Rows the subtitle pane grows to while a song has subtitles.
+    pub subtitle_lines: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            roots: vec![expand_tilde("~/Music")],
+            extensions: vec!["mp3".into(), "flac".into()],
+            exclude: Vec::new(),
+            follow_symlinks: true,
+            watch: true,
+            playlists: expand_tilde("~/.config/neocrystal/playlists"),
+            volume: 50,
+            volume_step: 1,
+            seek_step: Duration::from_secs(5),
+            crossfade: Duration::ZERO,
+            shuffle: ShuffleMode::Off,
+            seed: None,
+            repeat: RepeatMode::All,
+            sliding_speed: Duration::from_millis(300),
+            mouse: true,
+            tag_titles: false,
+            rpc: true,
+            mpris: true,
+            subtitle_lookup: SubtitleLookup::Sidecar,
+            subtitle_dirs: Vec::new(),
+            subtitle_formats: subtitle::FORMATS.iter().map(|f| f.to_string()).collect(),
+            subtitle_lines: 3,
+        }
+    }
+}
+
+pub const USAGE: &str = "usage: neocrystal [options]
+  -c, --config FILE       read FILE instead of ~/.config/neocrystal/config.toml
+  -d, --dir DIR           library root, can be given more than once (replaces the config's roots)
+  -e, --ext LIST          comma separated file extensions, e.g. mp3,flac,ogg
+  -x, --exclude GLOB      skip files matching GLOB, can be given more than once
+      --no-symlinks       don't follow symbolic links while scanning the library
+      --no-watch          don't watch the library for changes
+      --playlists DIR     keep playlists in DIR
+      --volume N          initial volume, 0-100
+      --volume-step N     volume change per key press
+      --seek-step SECS    how far the seek keys jump, 1-600
+      --shuffle MODE      off, bag, album or weighted
+      --seed N            shuffle the same way every time
+      --repeat MODE       all, one or off
+      --sliding-speed MS  sliding title speed in milliseconds per character
+      --no-mouse          start with mouse support off
+      --tag-titles        show title tags instead of file names
+      --no-rpc            don't start Discord rich presence
+      --no-mpris          don't register on D-Bus
+      --subtitles MODE    sidecar or off
+      --subtitle-formats LIST  comma separated sidecar formats in lookup order, e.g. lrc,ass
+      --subtitle-lines N  rows of the subtitle pane, 1-10
+  -h, --help              show this";
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        home_dir().map(|h| h.join(".config").join("neocrystal").join("config.toml"))
+    }
+
+    /// Config file (default location unless --config says otherwise) with the
+    /// command line on top. Problems in the file are returned, bad flags are an Err.
+    pub fn load(args: &[String]) -> Result<(Self, Vec<String>), String> {
+        let mut file = Self::default_path();
+        let mut explicit = false;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if arg == "-c" || arg == "--config" {
+                file = iter.next().map(PathBuf::from);
+                explicit = true;
+            }
+        }
+        let mut config = Self::default();
+        let mut problems = Vec::new();
+        if let Some(file) = file {
+            match fs::read_to_string(&file) {
+                Ok(buf) => problems = config.apply_file(&buf, &file.display().to_string()),
+                Err(e) if explicit => return Err(format!("{}: {}", file.display(), e)),
+                Err(_) => (),
+            }
+        }
+        config.apply_args(args)?;
+        Ok((config, problems))
+    }
+
+    pub fn apply_file(&mut self, buf: &str, file: &str) -> Vec<String> {
+        let (entries, mut problems) = parse_toml(buf, file);
+        for entry in entries {
+            let wrong = |what: &str| {
+                format!(
+                    "{}:{}: `{}` should be {}",
+                    file, entry.line, entry.key, what
+                )
+            };
+            let problem = match (entry.section.as_str(), entry.key.as_str(), &entry.value) {
+                ("library", "roots", Value::List(roots)) => {
+                    self.roots = roots.iter().map(|r| expand_tilde(r)).collect();
+                    None
+                }
+                ("library", "roots", Value::Str(root)) => {
+                    self.roots = vec![expand_tilde(root)];
+                    None
+                }
+                ("library", "extensions", Value::List(exts)) => {
+                    self.extensions = exts.iter().map(normalize_ext).collect();
+                    None
+                }
+                ("library", "exclude", Value::List(globs)) => {
+                    let (patterns, bad) = compile_globs(globs);
+                    self.exclude = patterns;
+                    bad.map(|g| format!("{}:{}: bad exclude glob `{}`", file, entry.line, g))
+                }
+                ("playback", "volume", Value::Int(v)) if (0..=100).contains(v) => {
+                    self.volume = *v as u8;
+                    None
+                }
+                ("playback", "volume_step", Value::Int(v)) if (1..=100).contains(v) => {
+                    self.volume_step = *v as u8;
+                    None
+                }
+                ("playback", "seek_step", Value::Int(v)) if (1..=600).contains(v) => {
+                    self.seek_step = Duration::from_secs(*v as u64);
+                    None
+                }
+                ("playback", "crossfade", Value::Int(v)) if (0..=10).contains(v) => {
+                    self.crossfade = Duration::from_secs(*v as u64);
+                    None
+                }
+                ("playback", "shuffle", Value::Str(s)) if ShuffleMode::parse(s).is_some() => {
+                    self.shuffle = ShuffleMode::parse(s).unwrap();
+                    None
+                }
+                ("playback", "repeat", Value::Str(s)) if RepeatMode::parse(s).is_some() => {
+                    self.repeat = RepeatMode::parse(s).unwrap();
+                    None
+                }
+                ("playback", "seed", Value::Int(v)) if *v >= 0 => {
+                    self.seed = Some(*v as u64);
+                    None
+                }
+                ("ui", "sliding_speed", Value::Int(v)) if *v > 0 => {
+                    self.sliding_speed = Duration::from_millis(*v as u64);
+                    None
+                }
+                ("ui", "mouse", Value::Bool(b)) => {
+                    self.mouse = *b;
+                    None
+                }
+                ("ui", "tag_titles", Value::Bool(b)) => {
+                    self.tag_titles = *b;
+                    None
+                }
+                ("integrations", "rpc", Value::Bool(b)) => {
+                    self.rpc = *b;
+                    None
+                }
+                ("integrations", "mpris", Value::Bool(b)) => {
+                    self.mpris = *b;
+                    None
+                }
+                ("subtitles", "lookup", Value::Str(s)) if parse_lookup(s).is_some() => {
+                    self.subtitle_lookup = parse_lookup(s).unwrap();
+                    None
+                }
+                ("subtitles", "lines", Value::Int(v)) if (1..=10).contains(v) => {
+                    self.subtitle_lines = *v as usize;
+                    None
+                }
+                ("library", "follow_symlinks", Value::Bool(b)) => {
+                    self.follow_symlinks = *b;
+                    None
+                }
+                ("library", "watch", Value::Bool(b)) => {
+                    self.watch = *b;
+                    None
+                }
+                ("library", "playlists", Value::Str(dir)) => {
+                    self.playlists = expand_tilde(dir);
+                    None
+                }
+                ("subtitles", "dirs", Value::List(dirs)) => {
+                    self.subtitle_dirs = dirs.iter().map(|d| expand_tilde(d)).collect();
+                    None
+                }
+                ("subtitles", "formats", Value::List(formats)) => match parse_formats(formats) {
+                    Ok(formats) => {
+                        self.subtitle_formats = formats;
+                        None
+                    }
+                    Err(e) => Some(format!("{}:{}: {}", file, entry.line, e)),
+                },
+                ("library", "roots" | "extensions" | "exclude", _)
+                | ("subtitles", "dirs" | "formats", _) => Some(wrong("a list of strings")),
+                ("library", "playlists", _) => Some(wrong("a directory")),
+                ("playback", "volume", _) => Some(wrong("a number from 0 to 100")),
+                ("playback", "volume_step", _) => Some(wrong("a number from 1 to 100")),
+                ("playback", "seek_step", _) => Some(wrong("a number of seconds from 1 to 600")),
+                ("playback", "crossfade", _) => Some(wrong("a number of seconds from 0 to 10")),
+                ("playback", "shuffle", _) => {
+                    Some(wrong("\"off\", \"bag\", \"album\" or \"weighted\""))
+                }
+                ("playback", "seed", _) => Some(wrong("a positive number")),
+                ("playback", "repeat", _) => Some(wrong("\"all\", \"one\" or \"off\"")),
+                ("ui", "sliding_speed", _) => Some(wrong("a positive number of milliseconds")),
+                ("ui", "mouse" | "tag_titles", _)
+                | ("integrations", "rpc" | "mpris", _)
+                | ("library", "follow_symlinks" | "watch", _) => Some(wrong("true or false")),
+                ("subtitles", "lookup", _) => Some(wrong("\"sidecar\" or \"off\"")),
+                ("subtitles", "lines", _) => Some(wrong("a number from 1 to 10")),
+                (section, key, _) => Some(format!(
+                    "{}:{}: unknown setting `{}` in [{}]",
+                    file, entry.line, key, section
+                )),
+            };
+            problems.extend(problem);
+        }
+        problems
+    }
+
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        let mut roots = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value", arg))
+            };
+            match arg.as_str() {
+                "-c" | "--config" => {
+                    value()?;
+                }
+                "-d" | "--dir" => roots.push(expand_tilde(&value()?)),
+                "-e" | "--ext" => {
+                    self.extensions = value()?
+                        .split(',')
+                        .filter(|e| !e.trim().is_empty())
+                        .map(normalize_ext)
+                        .collect();
+                }
+                "-x" | "--exclude" => {
+                    let glob = value()?;
+                    let pattern =
+                        Pattern::new(&glob).map_err(|e| format!("bad glob `{}`: {}", glob, e))?;
+                    self.exclude.push(pattern);
+                }
+                "--volume" => self.volume = parse_number(arg, &value()?, 0, 100)?,
+                "--volume-step" => self.volume_step = parse_number(arg, &value()?, 1, 100)?,
+                "--seek-step" => {
+                    let secs: u64 = parse_number(arg, &value()?, 1, 600)?;
+                    self.seek_step = Duration::from_secs(secs);
+                }
+                "--shuffle" => {
+                    let mode = value()?;
+                    self.shuffle = ShuffleMode::parse(&mode).ok_or_else(|| {
+                        format!(
+                            "--shuffle takes off, bag, album or weighted, not `{}`",
+                            mode
+                        )
+                    })?;
+                }
+                "--repeat" => {
+                    let mode = value()?;
+                    self.repeat = RepeatMode::parse(&mode)
+                        .ok_or_else(|| format!("--repeat takes all, one or off, not `{}`", mode))?;
+                }
+                "--seed" => self.seed = Some(parse_number(arg, &value()?, 0, u64::MAX)?),
+                "--sliding-speed" => {
+                    let ms: u64 = parse_number(arg, &value()?, 1, u64::MAX)?;
+                    self.sliding_speed = Duration::from_millis(ms);
+                }
+                "--no-symlinks" => self.follow_symlinks = false,
+                "--no-watch" => self.watch = false,
+                "--playlists" => self.playlists = expand_tilde(&value()?),
+                "--no-mouse" => self.mouse = false,
+                "--tag-titles" => self.tag_titles = true,
+                "--no-rpc" => self.rpc = false,
+                "--no-mpris" => self.mpris = false,
+                "--subtitles" => {
+                    let mode = value()?;
+                    self.subtitle_lookup = parse_lookup(&mode).ok_or_else(|| {
+                        format!("--subtitles takes sidecar or off, not `{}`", mode)
+                    })?;
+                }
+                "--subtitle-formats" => {
+                    let list = value()?;
+                    let formats: Vec<&str> =
+                        list.split(',').filter(|f| !f.trim().is_empty()).collect();
+                    self.subtitle_formats = parse_formats(&formats)?;
+                }
+                "--subtitle-lines" => self.subtitle_lines = parse_number(arg, &value()?, 1, 10)?,
+                other => return Err(format!("unknown option `{}`", other)),
+            }
+        }
+        if !roots.is_empty() {
+            self.roots = roots;
+        }
+        Ok(())
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|p| p.matches_path(path))
+    }
+
+    /// Where a song's subtitle would be, if there is one. Formats go in the configured
+    /// order, for each the song's own directory comes first, then the configured dirs.
+    pub fn find_subtitle(&self, song: &str) -> Option<PathBuf> {
+        if self.subtitle_lookup == SubtitleLookup::Off {
+            return None;
+        }
+        let song = Path::new(song);
+        let parent = song.parent().unwrap_or(Path::new(""));
+        let dirs: Vec<PathBuf> = std::iter::once(parent.to_path_buf())
+            .chain(self.subtitle_dirs.iter().map(|d| parent.join(d)))
+            .collect();
+        self.subtitle_formats.iter().find_map(|ext| {
+            let name = song.with_extension(ext);
+            let name = name.file_name()?;
+            dirs.iter()
+                .map(|dir| dir.join(name))
+                .find(|candidate| candidate.is_file())
+        })
+    }
+}
+
+fn normalize_ext(ext: impl AsRef<str>) -> String {
+    ext.as_ref()
+        .trim()
+        .trim_start_matches('.')
+        .to_ascii_lowercase()
+}
+
+/// A lookup order of sidecar formats, all of them ones neocrystal can read.
+fn parse_formats(formats: &[impl AsRef<str>]) -> Result<Vec<String>, String> {
+    let formats: Vec<String> = formats.iter().map(normalize_ext).collect();
+    match formats
+        .iter()
+        .find(|f| !subtitle::FORMATS.contains(&f.as_str()))
+    {
+        Some(bad) => Err(format!(
+            "unknown subtitle format `{}`, known are {}",
+            bad,
+            subtitle::FORMATS.join(", ")
+        )),
+        None => Ok(formats),
+    }
+}
+
+fn parse_lookup(s: &str) -> Option<SubtitleLookup> {
+    match s {
+        "sidecar" => Some(SubtitleLookup::Sidecar),
+        "off" => Some(SubtitleLookup::Off),
+        _ => None,
+    }
+}
+
+fn parse_number<T: std::str::FromStr + PartialOrd + std::fmt::Display>(
+    flag: &str,
+    value: &str,
+    min: T,
+    max: T,
+) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(n) if n >= min && n <= max => Ok(n),
+        _ => Err(format!("{} takes a number from {} to {}", flag, min, max)),
+    }
+}
+
+/// Compiles what it can, returns the first glob that didn't compile.
+fn compile_globs(globs: &[String]) -> (Vec<Pattern>, Option<String>) {
+    let mut bad = None;
+    let patterns = globs
+        .iter()
+        .filter_map(|g| match Pattern::new(&expand_tilde(g)) {
+            Ok(p) => Some(p),
+            Err(_) => {
+                bad.get_or_insert_with(|| g.clone());
+                None
+            }
+        })
+        .collect();
+    (patterns, bad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_subset() {
+        let (entries, problems) = parse_toml(
+            "# top\n[ui]\nmouse = false # trailing\nspeed = -3\n[library]\nroots = [\"a\", 'b',]\nbroken\nx = [\"open\"\n",
+            "config.toml",
+        );
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(entries[0].value, Value::Bool(false)));
+        assert_eq!(entries[0].section, "ui");
+        assert!(matches!(entries[1].value, Value::Int(-3)));
+        assert!(matches!(&entries[2].value, Value::List(l) if l == &["a", "b"]));
+        assert_eq!(
+            problems,
+            vec![
+                "config.toml:7: expected `key = value`",
+                "config.toml:8: can't read the value of `x`"
+            ]
+        );
+    }
+
+    #[test]
+    fn file_then_flags() {
+        let mut config = Config::default();
+        let problems = config.apply_file(
+            "[playback]\nvolume = 80\nvolume_step = 0\n[library]\nextensions = [\".MP3\", \"ogg\"]\nfoo = 1\n",
+            "c",
+        );
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("volume_step"));
+        assert!(problems[1].contains("unknown setting `foo`"));
+        assert_eq!(config.volume, 80);
+        assert_eq!(config.extensions, vec!["mp3", "ogg"]);
+
+        let args: Vec<String> = ["--volume", "20", "-d", "/a", "-d", "/b", "--no-rpc"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        config.apply_args(&args).unwrap();
+        assert_eq!(config.volume, 20);
+        assert_eq!(config.roots, vec!["/a", "/b"]);
+        assert!(!config.rpc);
+        assert!(config.apply_args(&["--volume".to_string()]).is_err());
+        assert!(config.apply_args(&["--bogus".to_string()]).is_err());
+    }
+
+    #[test]
+    fn seek_step() {
+        let mut config = Config::default();
+        assert_eq!(config.seek_step, Duration::from_secs(5));
+        let problems = config.apply_file("[playback]\nseek_step = 30\n", "c");
+        assert!(problems.is_empty());
+        assert_eq!(config.seek_step, Duration::from_secs(30));
+        let problems = config.apply_file("[playback]\nseek_step = 0\n", "c");
+        assert!(problems[0].contains("seek_step"));
+        assert_eq!(config.seek_step, Duration::from_secs(30));
+
+        let args = ["--seek-step".to_string(), "10".to_string()];
+        config.apply_args(&args).unwrap();
+        assert_eq!(config.seek_step, Duration::from_secs(10));
+        assert!(
+            config
+                .apply_args(&["--seek-step".to_string(), "0".to_string()])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn crossfade_setting() {
+        let mut config = Config::default();
+        assert!(config.crossfade.is_zero());
+        let problems = config.apply_file("[playback]\ncrossfade = 4\n", "c");
+        assert!(problems.is_empty());
+        assert_eq!(config.crossfade, Duration::from_secs(4));
+        let problems = config.apply_file("[playback]\ncrossfade = 11\n", "c");
+        assert!(problems[0].contains("crossfade"));
+        assert_eq!(config.crossfade, Duration::from_secs(4));
+    }
+
+    #[test]
+    fn shuffle_settings() {
+        let mut config = Config::default();
+        let problems = config.apply_file("[playback]\nshuffle = \"album\"\nseed = 5\n", "c");
+        assert!(problems.is_empty());
+        assert_eq!(config.shuffle, ShuffleMode::Album);
+        assert_eq!(config.seed, Some(5));
+        let problems = config.apply_file("[playback]\nshuffle = \"yes\"\nseed = -1\n", "c");
+        assert_eq!(problems.len(), 2);
+        assert_eq!(config.shuffle, ShuffleMode::Album);
+
+        let args: Vec<String> = ["--shuffle", "bag", "--repeat", "one"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        config.apply_args(&args).unwrap();
+        assert_eq!(config.shuffle, ShuffleMode::Bag);
+        assert_eq!(config.repeat, RepeatMode::One);
+        assert!(
+            config
+                .apply_args(&["--shuffle".to_string(), "on".to_string()])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn subtitle_formats_decide_the_sidecar() {
+        let dir = std::env::temp_dir().join(format!("neocrystal-subs-{}", std::process::id()));
+        fs::create_dir_all(dir.join("subs")).unwrap();
+        fs::write(dir.join("song.lrc"), "").unwrap();
+        fs::write(dir.join("subs").join("song.ass"), "").unwrap();
+        let song = dir.join("song.flac").display().to_string();
+
+        let mut config = Config {
+            subtitle_dirs: vec!["subs".into()],
+            ..Config::default()
+        };
+        assert_eq!(
+            config.find_subtitle(&song),
+            Some(dir.join("subs").join("song.ass"))
+        );
+        let problems = config.apply_file("[subtitles]\nformats = [\"LRC\", \"ass\"]\n", "c");
+        assert!(problems.is_empty());
+        assert_eq!(config.find_subtitle(&song), Some(dir.join("song.lrc")));
+        assert!(
+            config
+                .apply_args(&["--subtitle-formats".into(), "ass,idx".into()])
+                .is_err()
+        );
+        config
+            .apply_args(&["--subtitle-formats".into(), "ass".into()])
+            .unwrap();
+        fs::remove_file(dir.join("subs").join("song.ass")).unwrap();
+        assert_eq!(config.find_subtitle(&song), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,44 +1,29 @@
 extern crate glob;
 extern crate pancurses;
-use super::general::{Action, GeneralState};
-use super::subtitle::PreciseSubtitleImport;
+use super::general::{Action, GeneralState, View};
+use crate::modules::songs::RepeatMode;
 use crate::modules::audio::{AudioCommand, AudioReportAction};
 #[cfg(not(target_os = "windows"))]
 use crate::modules::dbus::spawn_mpris;
 #[cfg(not(target_os = "windows"))]
 use crate::modules::mouse::{self};
+use crate::modules::config::Config;
+use crate::modules::library::{LibraryEvent, spawn_load, spawn_watch};
+use crate::modules::keymap::{KeyAction, KeyName, Keymap};
+use crate::modules::loudness::spawn_analysis;
+use crate::modules::playlists::Playlists;
 use crate::modules::presence;
 use pancurses::{Input, initscr};
+use std::path::Path;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread::{self};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use super::{
     curses::*,
     presence::{RpcCommand, rpc_handler},
-    songs::absolute_index,
 };
 
-pub const UP: char = 'u';
-pub const DOWN: char = 'j';
-pub const LEFT: char = 'n';
-pub const RIGHT: char = 'm';
-pub const SHUFFLE: char = 'f';
-pub const PLAY: char = 'p';
-pub const BLACKLIST: char = 'b';
-pub const STOP: char = 's';
-pub const RESUME: char = 'r';
-pub const LOOP: char = 'l';
-pub const SPECIAL: char = 'o';
-pub const QUIT: char = 'q';
-pub const SEARCH: char = 'h';
-pub const FULL: char = 'g';
-pub const CHANGE: char = 'c';
-pub const SETNEXT: char = 'e';
-pub const DESEL: char = 'd';
-pub const SETPLAYLIST: char = 'v';
-pub const MOUSE_SUPPORT: char = 't';
-
 /// macro: get_input_or_report
 /// try to get input from pancurses:
 /// success -> return the key
@@ -47,6 +32,7 @@ pub const MOUSE_SUPPORT: char = 't';
 ///                 check if fcalc is smaller than 100 milliseconds && info belongs to the current song
 ///                 true -> return f13 meaning song ended. 100 milliseconds because get_pos is inconsistent
 ///                 false -> return f14 meaning a duration is sent, triggering rpc things and such.
+///      -> audio thread moved into the preloaded track -> stash the path and return f0
 ///      -> fail -> check d-bus commands
 macro_rules! get_input_or_report {
     ($window:expr, $comm_rx:expr, $general:expr, $loctimer:expr, $timeout_ms:expr) => {{
@@ -65,30 +51,26 @@ macro_rules! get_input_or_report {
                     crate::AudioReportAction::EOF => {
                         Some(pancurses::Input::KeyF13)
                     }
+                    crate::AudioReportAction::Advanced(path) => {
+                        $general.gapless.crossed = Some(path);
+                        Some(pancurses::Input::KeyF0)
+                    }
                     _ => Some(pancurses::Input::KeyF15),
                 },
                 Err(_) => None,
             }
         });
 
-        // 2) If no real input, but D-Bus action exists, synthesize equivalent key
+        // 2) If no real input, but D-Bus action exists, wake the loop up. The action
+        //    itself is turned into a KeyAction there
         if key.is_none() && $general.action != Action::Nothing {
-            if let Some(nk) = crate::modules::mouse::action_to_key($general.action, &mut $general) {
-                key = Some(nk);
-            }
+            key = Some(pancurses::Input::KeyF15);
         }
 
         key
     }};
 }
 
-fn replace_extension(path: &str, new_ext: &str) -> String {
-    let p = std::path::Path::new(path);
-    p.with_extension(new_ext)
-        .to_string_lossy()
-        .to_string()
-}
-
 #[derive(PartialEq, Debug)]
 #[repr(u16)]
 pub enum Magic {
@@ -126,18 +108,41 @@ pub enum Event {
     Audio(AudioReportAction),
 }
 
-pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAction>) -> bool {
+/// How far into a song previous restarts it instead of going back.
+const RESTART_AFTER: Duration = Duration::from_secs(3);
+
+pub fn crystal_manager(
+    tx: Sender<AudioCommand>,
+    comm_rx: Receiver<AudioReportAction>,
+    config: Config,
+    mut problems: Vec<String>,
+) -> bool {
     let mut window = initscr();
     let (dbus_action_tx, dbus_action_rx): (Sender<Action>, Receiver<Action>) = mpsc::channel();
-    let mut general: GeneralState = GeneralState::new();
+    let (loudness_tx, loudness_rx) = mpsc::channel::<(String, f32)>();
+    let (keymap, key_problems) = Keymap::load();
+    problems.extend(key_problems);
+    let mpris_enabled = config.mpris;
+    let rpc_enabled = config.rpc;
+    let (playlists, list_problems) = Playlists::load(Path::new(&config.playlists));
+    problems.extend(list_problems);
+    let mut general: GeneralState = GeneralState::new(config, playlists);
+    let (library_tx, library_rx) = mpsc::channel::<LibraryEvent>();
+    // dropping the watcher stops it, so it lives as long as this function
+    let _watcher = general
+        .config
+        .watch
+        .then(|| spawn_watch(general.config.clone(), library_tx.clone()))
+        .flatten();
+    spawn_load(general.config.clone(), library_tx);
 
     #[cfg(not(target_os = "windows"))]
-    let mpris = spawn_mpris(dbus_action_tx.clone());
+    let mpris = mpris_enabled.then(|| spawn_mpris(dbus_action_tx.clone()));
 
     let mut page = PageData::new();
 
     let rpc_comm = {
-        let (rpc_comm, receiver) = presence::RpcCommunication::new();
+        let (rpc_comm, receiver) = presence::RpcCommunication::new(rpc_enabled);
         if let Some(rx) = receiver {
             let _rpc_thread = thread::spawn(move || {
                 rpc_handler(rx);
@@ -147,8 +152,12 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
     };
 
     init_curses(&mut window);
-    autoalloc(&mut general);
+    relayout(&mut general, &window);
     draw_all(&mut general, &mut page);
+    if let Some(problem) = problems.first() {
+        // the subtitle line is free until something plays, all problems are printed on exit
+        draw_subtitle(&mut general, Some(problem));
+    }
     loop {
         if general.state.needs_update {
             update(&mut general, &mut window);
@@ -156,14 +165,17 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
         }
         if general.state.needs_dbus {
             #[cfg(not(target_os = "windows"))]
-            {
+            if let Some(mpris) = &mpris {
                 let mut s = mpris.state.lock().unwrap();
+                s.repeat = general.songs.repeat;
 
-                if general.songs.current_index == usize::MAX {
+                if general.songs.current.is_none() {
                     s.playback_status = 2;
                     s.title = "Nothing".into();
                     s.artist = vec!["Nothing".into()];
                     s.length_us = 0;
+                    s.track = None;
+                    s.song = None;
                 } else if general.songs.stophandler {
                     s.playback_status = 1;
                 } else {
@@ -171,12 +183,54 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
                     s.title = general.songs.current_name();
                     s.artist = vec![general.songs.current_artist()];
                     s.length_us = general.songs.get_duration().as_micros() as i64;
-                }
+                    s.track = general.songs.current;
+                    s.song = general
+                        .songs
+                        .current
+                        .and_then(|id| general.songs.song(id))
+                        .cloned();
+                }
+                drop(s);
+                mpris.emit();
             }
-            #[cfg(not(target_os = "windows"))]
-            mpris.emit();
             general.state.needs_dbus = false;
         }
+        sync_preload(&mut general, &tx);
+        while let Ok((path, gain)) = loudness_rx.try_recv() {
+            if general.songs.set_analyzed_gain(&path, gain) {
+                let _ = tx.send(AudioCommand::SetGain(
+                    general.songs.current_gain(general.state.gain_mode),
+                ));
+            }
+        }
+        let mut library_changed = false;
+        while let Ok(event) = library_rx.try_recv() {
+            match event {
+                LibraryEvent::Found(batch) => {
+                    general.songs.add(batch);
+                    library_changed = true;
+                }
+                LibraryEvent::Removed(paths) => {
+                    library_changed |= general.songs.remove(paths);
+                }
+                LibraryEvent::Done(mut report) => {
+                    // don't cover up a config problem, or a subtitle that started meanwhile
+                    if problems.is_empty() && general.subtitle.is_none() {
+                        draw_subtitle(&mut general, Some(&report.summary()));
+                    }
+                    problems.append(&mut report.failed);
+                }
+            }
+        }
+        library_changed |= general.songs.sweep();
+        if library_changed {
+            clamp_cursor(&mut general);
+            page.draw_changed_moved_page(&mut general);
+            page.draw_indicators(&mut general);
+            draw_page(&mut general);
+            general.state.needs_update = true;
+            general.state.needs_dbus = true;
+        }
         while let Ok(action) = dbus_action_rx.try_recv() {
             general.action = action;
         }
@@ -184,34 +238,61 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
         // if nothing is there to catch, it will just skip after 10 milliseconds           there
         let key_opt = get_input_or_report!(window, comm_rx, general, general.timer, 10);
 
-        if let Some(mut key) = key_opt {
+        if let Some(key) = key_opt {
             general.state.needs_update = true;
             if general.searchquery.mode != 0 {
                 match key {
                     Input::KeyEnter | Input::Character('\n') => {
                         match general.searchquery.mode {
                             1 => {
-                                general.songs.search(&general.searchquery.query);
-                                general.index.index = 0;
-                                general.index.page = 1;
-                                page.draw_changed_moved_page(&mut general);
-                                page.draw_indicators(&mut general);
+                                if let Err(e) = general.songs.search(&general.searchquery.query) {
+                                    // the prompt stays open to fix the query
+                                    general.searchquery.error = Some(e.to_string());
+                                    draw_header(&mut general);
+                                    continue;
+                                }
+                                search_changed(&mut general, &mut page);
                             }
                             2 => {
-                                general.songs.set_artist(
-                                    general.songs.match_c(),
-                                    &general.searchquery.query,
-                                );
+                                if let Some(id) = general.songs.current {
+                                    general.songs.set_artist(id, &general.searchquery.query);
+                                }
                                 draw_artist(&mut general);
                                 general.state.needs_dbus = true;
                             }
                             3 => {
-                                general.songs.set_playlist(
-                                    general.songs.match_c(),
-                                    &general.searchquery.query,
-                                );
+                                if let Some(id) = general.songs.current {
+                                    general.songs.set_album(id, &general.searchquery.query);
+                                }
                                 draw_playlist(&mut general);
                             }
+                            4..=7 => {
+                                let query = general.searchquery.query.clone();
+                                let edited = match general.searchquery.mode {
+                                    4 => general.add_to_playlist(&query),
+                                    5 => general.new_playlist(&query),
+                                    6 => general.rename_playlist(&query),
+                                    _ if query.trim().eq_ignore_ascii_case("y") => {
+                                        general.delete_playlist()
+                                    }
+                                    _ => Err("kept it".to_string()),
+                                };
+                                playlist_edited(&mut general, &mut page, edited);
+                            }
+                            8 => {
+                                let imported =
+                                    general.import_playlist(&general.searchquery.query.clone());
+                                let edited = imported.map(|(message, mut missing)| {
+                                    problems.append(&mut missing);
+                                    message
+                                });
+                                playlist_edited(&mut general, &mut page, edited);
+                            }
+                            9 => {
+                                let exported = general.export_list(&general.searchquery.query);
+                                let (Ok(message) | Err(message)) = exported;
+                                draw_subtitle(&mut general, Some(&message));
+                            }
                             _ => {}
                         }
                         general.searchquery.default();
@@ -220,11 +301,19 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
                     }
                     Input::KeyBackspace | Input::Character('\x7f') | Input::Character('\x08') => {
                         general.searchquery.query.pop();
+                        general.searchquery.error = None;
+                        if general.searchquery.mode == 1 {
+                            search_live(&mut general, &mut page);
+                        }
                         draw_header(&mut general);
                         continue;
                     }
                     Input::Character(i) => {
                         general.searchquery.query.push(i);
+                        general.searchquery.error = None;
+                        if general.searchquery.mode == 1 {
+                            search_live(&mut general, &mut page);
+                        }
                         draw_header(&mut general);
                         continue;
                     }
@@ -241,115 +330,93 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
                     }
                 }
             }
+            let bound = match key {
+                // alt comes in as esc followed by the key
+                Input::Character('\x1b') => match window.getch() {
+                    Some(Input::Character(c)) => KeyName::Alt(c),
+                    // not alt, the next key is read on the next pass
+                    Some(next) => {
+                        window.ungetch(&next);
+                        KeyName::Plain(key)
+                    }
+                    None => KeyName::Plain(key),
+                },
+                _ => KeyName::Plain(key),
+            };
+            let mut command = keymap.resolve(bound);
             if general.action != Action::Nothing {
                 if let Some(nk) = mouse::action_to_key(general.action, &mut general) {
-                    key = nk;
+                    command = Some(nk);
                 }
             }
-            match key {
-                Input::KeyNext => {
+            let Some(command) = command else {
+                general.action = Action::Nothing;
+                continue;
+            };
+            match command {
+                KeyAction::Next => {
                     // song ended but ignore loop. this is used from D-Bus or keyboard but mainly dbus
                     if general.songs.stophandler {
                         continue;
+                    } else if general.songs.set_by_next().is_err() {
+                        playback_ended(&mut general, &mut page, &tx, &rpc_comm);
                     } else {
-                        match general.songs.set_by_next() {
-                            Ok(_) => (),
-                            Err(_) => (),
-                        }
-                    }
-                    tx.send(AudioCommand::Play(general.songs.current_song_path()))
+                        tx.send(AudioCommand::Play(
+                            general.songs.current_song_path(),
+                            general.songs.current_gain(general.state.gain_mode),
+                        ))
                         .unwrap();
-                    general.timer.maxlen = general.songs.get_duration();
-                    general.timer.fcalc = general.timer.maxlen;
-                    let ass_path = replace_extension(&general.songs.current_song_path(), "ass");
-                    if std::path::Path::new(&ass_path).exists() {
-                        let mut sub = PreciseSubtitleImport::new();
-                        sub.asyncgate(&ass_path);
-                        general.subtitle = Some(sub);
-                    } else {
-                        general.subtitle = None;
-                        draw_subtitle(&mut general, None);
+                        track_changed(&mut general, &mut page);
                     }
-                    general.rpc.init();
-                    general.sliding.reset_to(general.songs.current_name());
-                    draw_artist(&mut general);
-                    draw_playlist(&mut general);
-                    draw_sliding(&mut general);
-                    draw_time_max(&mut general);
-                    draw_time_cur(&mut general);
-                    page.draw_indicators(&mut general);
-                    draw_rpc_indc(&mut general);
-                    general.state.needs_dbus = true;
                 }
-                Input::KeyPrevious => {
+                KeyAction::Previous => {
                     if general.songs.stophandler {
                         continue;
-                    } else {
-                        match general.songs.prev() {
-                            Ok(_) => (),
-                            Err(_) => (),
-                        }
                     }
-                    tx.send(AudioCommand::Play(general.songs.current_song_path()))
-                        .unwrap();
-                    general.timer.maxlen = general.songs.get_duration();
-                    general.timer.fcalc = general.timer.maxlen;
-                    let ass_path = replace_extension(&general.songs.current_song_path(), "ass");
-                    if std::path::Path::new(&ass_path).exists() {
-                        let mut sub = PreciseSubtitleImport::new();
-                        sub.asyncgate(&ass_path);
-                        general.subtitle = Some(sub);
+                    // a few seconds in, previous starts the song over like most players do
+                    let elapsed = general.timer.maxlen.saturating_sub(general.timer.fcalc);
+                    if elapsed > RESTART_AFTER || general.songs.prev().is_err() {
+                        tx.send(AudioCommand::SeekTo(Duration::ZERO)).unwrap();
+                        general.state.seeked = true;
+                        general.rpc.renew();
+                        draw_rpc_indc(&mut general);
                     } else {
-                        general.subtitle = None;
-                        draw_subtitle(&mut general, None);
+                        tx.send(AudioCommand::Play(
+                            general.songs.current_song_path(),
+                            general.songs.current_gain(general.state.gain_mode),
+                        ))
+                        .unwrap();
+                        track_changed(&mut general, &mut page);
                     }
-                    general.rpc.init();
-                    general.sliding.reset_to(general.songs.current_name());
-                    draw_artist(&mut general);
-                    draw_playlist(&mut general);
-                    draw_sliding(&mut general);
-                    draw_time_max(&mut general);
-                    draw_time_cur(&mut general);
-                    page.draw_indicators(&mut general);
-                    draw_rpc_indc(&mut general);
-                    general.state.needs_dbus = true;
                 }
-                Input::KeyF13 => {
+                KeyAction::TrackEnded => {
                     // song ended
                     if general.songs.stophandler {
                         continue;
-                    } else if !general.state.isloop {
-                        match general.songs.set_by_next() {
-                            Ok(_) => (),
-                            Err(_) => (),
-                        }
-                    }
-
-                    tx.send(AudioCommand::Play(general.songs.current_song_path()))
-                        .unwrap();
-                    general.timer.maxlen = general.songs.get_duration();
-                    general.timer.fcalc = general.timer.maxlen;
-                    let ass_path = replace_extension(&general.songs.current_song_path(), "ass");
-                    if std::path::Path::new(&ass_path).exists() {
-                        let mut sub = PreciseSubtitleImport::new();
-                        sub.asyncgate(&ass_path);
-                        general.subtitle = Some(sub);
+                    } else if general.songs.repeat != RepeatMode::One
+                        && general.songs.set_by_next().is_err()
+                    {
+                        playback_ended(&mut general, &mut page, &tx, &rpc_comm);
                     } else {
-                        general.subtitle = None;
-                        draw_subtitle(&mut general, None);
+                        tx.send(AudioCommand::Play(
+                            general.songs.current_song_path(),
+                            general.songs.current_gain(general.state.gain_mode),
+                        ))
+                        .unwrap();
+                        track_changed(&mut general, &mut page);
                     }
-                    general.rpc.init();
-                    general.sliding.reset_to(general.songs.current_name());
-                    draw_artist(&mut general);
-                    draw_playlist(&mut general);
-                    draw_sliding(&mut general);
-                    draw_time_max(&mut general);
-                    draw_time_cur(&mut general);
-                    page.draw_indicators(&mut general);
-                    draw_rpc_indc(&mut general);
-                    general.state.needs_dbus = true;
                 }
-                Input::KeyF14 => {
+                KeyAction::Advanced => {
+                    // gapless handover, audio thread is already playing the preloaded song
+                    let Some(path) = general.gapless.crossed.take() else {
+                        continue;
+                    };
+                    if general.songs.set_by_path(&path).is_err() {
+                        continue;
+                    }
+                    track_changed(&mut general, &mut page);
+                }
+                KeyAction::Tick => {
                     //duration sent
                     if general.rpc.timer <= Instant::now() && general.rpc.reinit {
                         general.handle_rpc(&rpc_comm, general.rpc.timer);
@@ -357,6 +424,15 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
                     }
                     draw_progress(&mut general);
                     draw_time_cur(&mut general);
+                    #[cfg(not(target_os = "windows"))]
+                    if let Some(mpris) = &mpris {
+                        let position = general.timer.elapsed().as_micros() as i64;
+                        mpris.state.lock().unwrap().position_us = position;
+                        if general.state.seeked {
+                            mpris.seeked(position);
+                            general.state.seeked = false;
+                        }
+                    }
                     if general.sliding.is_changing() {
                         draw_sliding(&mut general);
                     }
@@ -364,34 +440,35 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
                         let elapsed = general.timer.maxlen
                             .checked_sub(general.timer.fcalc)
                             .unwrap_or_default();
-                        let text = general.subtitle.as_ref().unwrap().get_from_time(elapsed);
-                        draw_subtitle(&mut general, text.as_deref());
+                        let width = general.ui.get_range(&Ownership::Subtitle).unwrap_or(46);
+                        let lines = general.subtitle.as_ref().unwrap().lines_at(elapsed, width);
+                        draw_subtitle_lines(&mut general, &lines);
                     }
                 }
-                Input::Character(QUIT) => {
+                KeyAction::Quit => {
                     tx.send(AudioCommand::Stop).unwrap();
                     break;
                 }
 
-                Input::KeyDown | Input::Character(DOWN) => {
+                KeyAction::Down => {
                     move_selection(Direction::Down, &mut general, &tx, &mut page);
                 }
 
-                Input::KeyUp | Input::Character(UP) => {
+                KeyAction::Up => {
                     move_selection(Direction::Up, &mut general, &tx, &mut page);
                 }
 
-                Input::Character(PLAY) => {
+                KeyAction::Play => {
+                    if general.open_playlist() {
+                        page.draw_changed_moved_page(&mut general);
+                        draw_header(&mut general);
+                    }
                     if !play_current_song(&mut general, &tx) {
                         continue;
                     };
-                    let ass_path = replace_extension(&general.songs.current_song_path(), "ass");
-                    if std::path::Path::new(&ass_path).exists() {
-                        let mut sub = PreciseSubtitleImport::new();
-                        sub.asyncgate(&ass_path);
-                        general.subtitle = Some(sub);
-                    } else {
-                        general.subtitle = None;
+                    general.load_subtitle();
+                    fit_subtitle_pane(&mut general, &mut page);
+                    if general.subtitle.is_none() {
                         draw_subtitle(&mut general, None);
                     }
                     general.rpc.init();
@@ -403,9 +480,10 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
                     page.draw_indicators(&mut general);
                     draw_rpc_indc(&mut general);
                     draw_progress(&mut general);
+                    list_changed(&mut general, &mut page);
                     match general.action {
                         #[cfg(feature = "mouse")]
-                        Action::Play(_, _) => {
+                        Action::Play(_) => {
                             page.draw_unchanged_moved_page(&mut general);
                         }
                         _ => (),
@@ -413,33 +491,37 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
                     general.state.needs_dbus = true;
                 }
 
-                Input::Character(SPECIAL) => {
+                KeyAction::Special => {
                     general.state.spint = !general.state.spint;
                 }
 
-                Input::Character(LOOP) => {
-                    general.state.isloop = !general.state.isloop;
+                KeyAction::Loop | KeyAction::RepeatChanged => {
+                    if command == KeyAction::Loop {
+                        general.songs.set_repeat(general.songs.repeat.cycle());
+                    }
                     draw_loop_indc(&mut general);
                     page.draw_indicators(&mut general);
+                    general.state.needs_dbus = true;
                 }
 
-                Input::Character(STOP) => {
+                KeyAction::Stop => {
                     general.songs.stop();
                     tx.send(AudioCommand::Pause).unwrap();
                     rpc_comm.send_message(RpcCommand::Clear);
                     general.subtitle = None;
+                    fit_subtitle_pane(&mut general, &mut page);
                     draw_subtitle(&mut general, None);
                     page.draw_indicators(&mut general);
                     general.state.needs_dbus = true;
                 }
 
-                Input::Character(BLACKLIST) => {
+                KeyAction::Blacklist => {
                     general.blacklist();
                     page.draw_indicators(&mut general);
                 }
 
-                Input::Character(RESUME) => {
-                    if general.songs.current_index == usize::MAX {
+                KeyAction::Resume => {
+                    if general.songs.current.is_none() {
                         continue;
                     }
                     general.songs.resume();
@@ -449,66 +531,207 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
                     draw_rpc_indc(&mut general);
                     general.state.needs_dbus = true;
                 }
-                Input::KeyRight | Input::Character(RIGHT) => {
-                    tx.send(AudioCommand::SeekForward).unwrap();
+                KeyAction::SeekForward => {
+                    tx.send(AudioCommand::SeekForward(general.timer.seek_step)).unwrap();
+                    general.state.seeked = true;
+                    general.rpc.renew();
+                    draw_rpc_indc(&mut general);
+                }
+                KeyAction::SeekBackward => {
+                    tx.send(AudioCommand::SeekBackward(general.timer.seek_step)).unwrap();
+                    general.state.seeked = true;
+                    general.rpc.renew();
+                    draw_rpc_indc(&mut general);
+                }
+                KeyAction::SeekTenth(tenth) => {
+                    // jump to 0%..90% of the track
+                    if general.songs.current.is_none() {
+                        continue;
+                    }
+                    let target = general.timer.tenth(tenth);
+                    tx.send(AudioCommand::SeekTo(target)).unwrap();
+                    general.state.seeked = true;
                     general.rpc.renew();
                     draw_rpc_indc(&mut general);
                 }
-                Input::KeyLeft | Input::Character(LEFT) => {
-                    tx.send(AudioCommand::SeekBackward).unwrap();
+                KeyAction::SeekPending => {
+                    // absolute seek from mouse or D-Bus, target is waiting in the timer
+                    let Some(target) = general.timer.seek.take() else {
+                        continue;
+                    };
+                    if general.songs.current.is_none() {
+                        continue;
+                    }
+                    tx.send(AudioCommand::SeekTo(target)).unwrap();
+                    general.state.seeked = true;
                     general.rpc.renew();
                     draw_rpc_indc(&mut general);
                 }
-                Input::Character(SHUFFLE) => {
+                KeyAction::Shuffle => {
                     general.songs.shuffle();
                     page.draw_indicators(&mut general);
                     draw_shuffle_indc(&mut general);
                 }
-                Input::Character(SEARCH) => {
+                KeyAction::Search => {
                     general.searchquery.to_mode(1);
                     draw_search(&mut general);
                 }
-                Input::Character(FULL) => {
+                KeyAction::Resize => {
+                    pancurses::resize_term(0, 0);
+                    relayout(&mut general, &window);
+                    page = PageData::new();
+                    window.clear();
+                    draw_all(&mut general, &mut page);
+                }
+                KeyAction::Full => {
                     window.clear();
                     draw_all(&mut general, &mut page);
                 }
-                Input::Character(CHANGE) => {
+                KeyAction::Change => {
                     general.searchquery.to_mode(2);
                     draw_search(&mut general);
                 }
-                Input::Character(SETPLAYLIST) => {
+                KeyAction::SetAlbum => {
                     general.searchquery.to_mode(3);
                     draw_search(&mut general);
                 }
-                Input::Character(SETNEXT) => {
-                    general.songs.set_next(absolute_index(
-                        general.index.index,
-                        general.index.page,
-                        general.songs.typical_page_size,
-                    ));
+                KeyAction::PlaylistAdd => {
+                    if general.selected().is_some() {
+                        general.searchquery.to_mode(4);
+                        draw_search(&mut general);
+                    }
+                }
+                KeyAction::PlaylistNew => {
+                    general.searchquery.to_mode(5);
+                    draw_search(&mut general);
+                }
+                KeyAction::PlaylistRename | KeyAction::PlaylistRemove
+                    if general.view == View::Playlists =>
+                {
+                    if general.row_count() != 0 {
+                        general.searchquery.to_mode(match command {
+                            KeyAction::PlaylistRename => 6,
+                            _ => 7,
+                        });
+                        draw_search(&mut general);
+                    }
+                }
+                KeyAction::PlaylistRename => {}
+                KeyAction::PlaylistImport => {
+                    general.searchquery.to_mode(8);
+                    draw_search(&mut general);
+                }
+                KeyAction::PlaylistExport => {
+                    general.searchquery.to_mode(9);
+                    draw_search(&mut general);
+                }
+                KeyAction::PlaylistRemove => {
+                    let edited = general.remove_from_playlist();
+                    playlist_edited(&mut general, &mut page, edited);
+                }
+                KeyAction::Library => {
+                    general.close_playlist();
+                    playlist_edited(&mut general, &mut page, Ok("all songs".to_string()));
+                }
+                KeyAction::SetNext => {
+                    if let Some(id) = general.selected() {
+                        general.songs.set_next(id);
+                    }
+                    list_changed(&mut general, &mut page);
+                }
+                KeyAction::QueueAdd => {
+                    if let Some(id) = general.selected() {
+                        general.songs.enqueue(id);
+                    }
+                    list_changed(&mut general, &mut page);
+                }
+                KeyAction::QueueAddAll => {
+                    general.songs.enqueue_all();
+                    list_changed(&mut general, &mut page);
+                }
+                KeyAction::QueueRemove => {
+                    match general.view {
+                        View::Queue => general.songs.dequeue(general.cursor()),
+                        _ => {
+                            if let Some(id) = general.selected() {
+                                general.songs.unqueue(id);
+                            }
+                        }
+                    }
+                    list_changed(&mut general, &mut page);
+                }
+                KeyAction::QueueUp | KeyAction::QueueDown => {
+                    if general.view != View::Queue {
+                        continue;
+                    }
+                    let up = command == KeyAction::QueueUp;
+                    if let Some(to) = general.songs.move_queued(general.cursor(), up) {
+                        general.set_cursor(to);
+                        list_changed(&mut general, &mut page);
+                    }
+                }
+                KeyAction::QueueClear => {
+                    general.songs.clear_queue();
+                    list_changed(&mut general, &mut page);
+                }
+                KeyAction::QueueView
+                | KeyAction::HistoryView
+                | KeyAction::PlaylistsView
+                | KeyAction::LyricsView => {
+                    general.toggle_view(match command {
+                        KeyAction::QueueView => View::Queue,
+                        KeyAction::HistoryView => View::History,
+                        KeyAction::LyricsView => View::Lyrics,
+                        _ => View::Playlists,
+                    });
+                    clamp_cursor(&mut general);
+                    page.draw_changed_moved_page(&mut general);
                     page.draw_indicators(&mut general);
+                    draw_header(&mut general);
                 }
-                Input::Character(DESEL) => {
+                KeyAction::Desel => {
                     general.state.desel = !general.state.desel;
                     page.draw_changed_moved_page(&mut general);
                 }
-                Input::KeyPPage => {
+                KeyAction::PageUp => {
                     change_page(Direction::Up, &mut general, &mut page);
                     page.draw_indicators(&mut general);
                     page.draw_changed_moved_page(&mut general);
                     draw_page(&mut general);
                 }
-                Input::KeyNPage => {
+                KeyAction::PageDown => {
                     change_page(Direction::Down, &mut general, &mut page);
                     page.draw_indicators(&mut general);
                     page.draw_changed_moved_page(&mut general);
                     draw_page(&mut general);
                 }
-                Input::Character(MOUSE_SUPPORT) => {
+                KeyAction::MouseSupport => {
                     general.state.mouse_support = !general.state.mouse_support;
                 }
-
-                _ => (),
+                KeyAction::Gain => {
+                    general.state.gain_mode = general.state.gain_mode.cycle();
+                    let _ = tx.send(AudioCommand::SetGain(
+                        general.songs.current_gain(general.state.gain_mode),
+                    ));
+                    draw_gain_indc(&mut general);
+                }
+                KeyAction::Analyze => {
+                    let paths = general.songs.unanalyzed_paths();
+                    let count = paths.len();
+                    let message = if count == 0 {
+                        "nothing to analyze, every song has a gain".to_string()
+                    } else if spawn_analysis(paths, loudness_tx.clone()) {
+                        format!("analyzing {} files", count)
+                    } else {
+                        "analysis already running".to_string()
+                    };
+                    draw_subtitle(&mut general, Some(&message));
+                }
+                KeyAction::Crossfade => {
+                    general.gapless.step_crossfade();
+                    let _ = tx.send(AudioCommand::SetCrossfade(general.gapless.crossfade));
+                    draw_crossfade_indc(&mut general);
+                }
             }
         }
         general.action = Action::Nothing;
@@ -516,23 +739,140 @@ pub fn crystal_manager(tx: Sender<AudioCommand>, comm_rx: Receiver<AudioReportAc
     match rpc_comm.send_message(RpcCommand::Stop) {
         _ => (),
     }
+    general.songs.save_plays();
     exit_curses(&mut window);
+    problems.append(&mut general.problems);
+    for problem in problems {
+        eprintln!("{}", problem);
+    }
     true
 }
 
+/// Everything that follows the current song changing under a running track:
+/// timer, subtitles, rpc, footer and indicators.
+fn track_changed(general: &mut GeneralState, page: &mut PageData) {
+    general.gapless.forget();
+    general.timer.maxlen = general.songs.get_duration();
+    general.timer.fcalc = general.timer.maxlen;
+    general.load_subtitle();
+    fit_subtitle_pane(general, page);
+    if general.subtitle.is_none() {
+        draw_subtitle(general, None);
+    }
+    general.rpc.init();
+    general.sliding.reset_to(general.songs.current_name());
+    draw_artist(general);
+    draw_playlist(general);
+    draw_sliding(general);
+    draw_time_max(general);
+    draw_time_cur(general);
+    list_changed(general, page);
+    draw_rpc_indc(general);
+    general.state.needs_dbus = true;
+}
+
+/// The list ran out with repeat off, same as stopping.
+fn playback_ended(
+    general: &mut GeneralState,
+    page: &mut PageData,
+    tx: &Sender<AudioCommand>,
+    rpc_comm: &presence::RpcCommunication,
+) {
+    general.songs.end();
+    let _ = tx.send(AudioCommand::Pause);
+    rpc_comm.send_message(RpcCommand::Clear);
+    general.subtitle = None;
+    fit_subtitle_pane(general, page);
+    draw_subtitle(general, None);
+    page.draw_indicators(general);
+    general.state.needs_dbus = true;
+}
+
+/// Shows how a playlist edit went on the subtitle line and redraws the list, which
+/// can be the edited playlist or the browser.
+fn playlist_edited(
+    general: &mut GeneralState,
+    page: &mut PageData,
+    edited: Result<String, String>,
+) {
+    let (Ok(message) | Err(message)) = edited;
+    draw_subtitle(general, Some(&message));
+    clamp_cursor(general);
+    page.draw_changed_moved_page(general);
+    page.draw_indicators(general);
+    draw_header(general);
+}
+
+/// Shows the top of the search results.
+fn search_changed(general: &mut GeneralState, page: &mut PageData) {
+    general.switch_view(View::Songs);
+    general.index.index = 0;
+    general.index.page = 1;
+    page.draw_changed_moved_page(general);
+    page.draw_indicators(general);
+}
+
+/// Searches while the query is typed. Half typed queries that don't parse yet leave the
+/// list alone, Enter says what's wrong with them.
+fn search_live(general: &mut GeneralState, page: &mut PageData) {
+    if general.songs.search(&general.searchquery.query).is_ok() {
+        search_changed(general, page);
+    }
+}
+
+/// Redraws the list after the queue or the history changed under it.
+fn list_changed(general: &mut GeneralState, page: &mut PageData) {
+    if general.view != View::Songs {
+        clamp_cursor(general);
+        page.draw_changed_moved_page(general);
+        draw_header(general);
+    }
+    page.draw_indicators(general);
+}
+
+/// Tells the audio thread what comes after the current track whenever that changes,
+/// so it can append it to the sink before the current one runs out.
+/// Looping a track or a queued next song never crossfades.
+fn sync_preload(general: &mut GeneralState, tx: &Sender<AudioCommand>) {
+    let mode = general.state.gain_mode;
+    let want = if general.songs.repeat == RepeatMode::One && !general.songs.stophandler {
+        (
+            Some((general.songs.current_song_path(), general.songs.current_gain(mode))),
+            false,
+        )
+    } else {
+        (general.songs.next_song(mode), !general.songs.next_is_queued())
+    };
+    if want != general.gapless.sent {
+        let _ = tx.send(AudioCommand::Preload(want.0.clone(), want.1));
+        general.gapless.sent = want;
+    }
+}
+
 pub fn play_current_song(general: &mut GeneralState, tx: &Sender<AudioCommand>) -> bool {
-    if general
-        .songs
-        .set_by_pindex(general.index.index, general.index.page)
-        != Err(0)
-    {
+    let played = match general.view {
+        View::Songs => general
+            .songs
+            .set_by_pindex(general.index.index, general.index.page),
+        View::Queue => general.songs.set_by_queue(general.cursor()),
+        View::History => match general.selected() {
+            Some(id) => general.songs.set_by_id(id),
+            None => Err(1),
+        },
+        View::Playlists | View::Lyrics => Err(0),
+    };
+    if played != Err(0) {
         if tx
-            .send(AudioCommand::Play(general.songs.current_song_path()))
+            .send(AudioCommand::Play(
+                general.songs.current_song_path(),
+                general.songs.current_gain(general.state.gain_mode),
+            ))
             .is_err()
         {
             return false;
         }
 
+        general.gapless.forget();
         general.timer.maxlen = general.songs.get_duration();
 
         general.timer.fcalc = general.timer.maxlen;
@@ -546,7 +886,7 @@ pub fn play_current_song(general: &mut GeneralState, tx: &Sender<AudioCommand>)
 
 pub fn change_page(dir: Direction, general: &mut GeneralState, page: &mut PageData) {
     let psize = general.songs.typical_page_size.max(1);
-    let total = general.songs.filtered_songs.len();
+    let total = general.row_count();
 
     if total == 0 {
         return;
@@ -596,7 +936,7 @@ pub fn move_selection(
         tx.send(AudioCommand::SetVolume(general.volume.as_f32()))
             .unwrap_or_else(|_| ());
         draw_vol_indc(general);
-    } else if !general.songs.filtered_songs.is_empty() {
+    } else if general.row_count() != 0 {
         match direction {
             Direction::Up => {
                 if general.index.index > 0 {
@@ -611,11 +951,7 @@ pub fn move_selection(
                 }
             }
             Direction::Down => {
-                let absolute = absolute_index(
-                    general.index.index,
-                    general.index.page,
-                    general.songs.typical_page_size,
-                ) < general.songs.filtered_songs.len() - 1;
+                let absolute = general.cursor() < general.row_count() - 1;
                 if general.index.index + 1 < general.songs.typical_page_size && absolute {
                     general.index.index += 1;
                     page.draw_unchanged_moved_page(general);
@@ -1,15 +1,24 @@
+use chrono::{DateTime, Local};
 use pancurses::{Window, mousemask};
 
-use crate::modules::{general::NcursesExec, utils::ReinitMode};
+use crate::modules::{
+    general::{NcursesExec, View},
+    loudness::GainMode,
+    songs::{RepeatMode, ShuffleMode, SongId},
+    subtitle::Shown,
+    tui_ir::ColorIntegerSize,
+    utils::{ReinitMode, fit_width},
+};
+use std::ops::Range;
 use std::time::Duration;
 use unicode_width::UnicodeWidthStr;
 
 use super::general::GeneralState;
 
 #[inline]
-pub fn calc(maxlen: Duration, curr: Duration) -> usize {
-    ((maxlen.as_secs_f64() - curr.as_secs_f64()) / (maxlen.as_secs_f64() / 15_f64))
-        .clamp(0.0, 15.0)
+pub fn calc(maxlen: Duration, curr: Duration, width: usize) -> usize {
+    ((maxlen.as_secs_f64() - curr.as_secs_f64()) / (maxlen.as_secs_f64() / width as f64))
+        .clamp(0.0, width as f64)
         .round() as usize
 }
 
@@ -25,6 +34,8 @@ pub fn to_mm_ss(duration: Duration) -> String {
 pub enum Ownership {
     Songs,
     Subtitle,
+    /// Rows above the subtitle line while a song has subtitles.
+    SubtitlePane,
     PlaylistPage,
     SongInd,
     Playlist,
@@ -38,75 +49,221 @@ pub enum Ownership {
     RpcVol,
     RpcInd,
     VolInd,
+    GainInd,
+    CrossInd,
     Search,
     Page,
     Progress,
 }
 
 pub fn switch_alloc(general: &mut GeneralState) {
-    general.ui.alloc(&Ownership::PlaylistPage, (2, 46), (1, 14));
+    let (rx, ry) = songs_region(general);
+    general.ui.alloc(&Ownership::PlaylistPage, rx, ry);
 }
 pub fn realloc(general: &mut GeneralState) {
-    general.ui.alloc(&Ownership::Songs, (2, 46), (1, 14));
+    let (rx, ry) = songs_region(general);
+    general.ui.alloc(&Ownership::Songs, rx, ry);
 }
 
+/// Subtitles in cyan, karaoke syllables that were sung in yellow.
+const SUBTITLE_COLOR: ColorIntegerSize = 9;
+const SUNG_COLOR: ColorIntegerSize = 4;
+
 pub fn draw_subtitle(general: &mut GeneralState, text: Option<&str>) {
-    let content = text.unwrap_or("");
-    let width = general.ui.get_range(&Ownership::Subtitle).unwrap_or(46);
-    let text_width = content.width();
-    let x = (width / 2).saturating_sub(text_width / 2);
-    general.ui.write(&Ownership::Subtitle, x, 0, content, 9);
+    let line = text.map(|text| Shown {
+        text: text.to_string(),
+        sung: Vec::new(),
+        column: 2,
+    });
+    draw_subtitle_lines(general, line.as_slice());
+}
+
+/// Subtitle lines from the bottom up, the last one on the separator and the ones
+/// before it in the pane above. Lines that don't fit are left out from the top.
+pub fn draw_subtitle_lines(general: &mut GeneralState, lines: &[Shown]) {
+    let pane = pane_rows(general);
+    let lines = &lines[lines.len().saturating_sub(pane + 1)..];
+    let (last, above) = match lines.split_last() {
+        Some((last, above)) => (Some(last), above),
+        None => (None, lines),
+    };
+    write_subtitle(general, Ownership::Subtitle, 0, 0, last);
+    for row in 0..pane {
+        // the pane starts one column early to cover the song indicators
+        let line = (row + above.len()).checked_sub(pane).map(|i| &above[i]);
+        write_subtitle(general, Ownership::SubtitlePane, 1, row, line);
+    }
+}
+
+/// A subtitle line in its column, with what the karaoke went over drawn as sung.
+fn write_subtitle(
+    general: &mut GeneralState,
+    id: Ownership,
+    left: usize,
+    row: usize,
+    line: Option<&Shown>,
+) {
+    let Some(range) = general.ui.get_range(&id) else {
+        return;
+    };
+    let width = range.saturating_sub(2 * left);
+    let Some(line) = line else {
+        general.ui.write(&id, width / 2, row, "", SUBTITLE_COLOR);
+        return;
+    };
+    let text = fit_width(&line.text, width);
+    let x = match line.column {
+        1 => 0,
+        3 => width.saturating_sub(text.width()),
+        _ => (width / 2).saturating_sub(text.width() / 2),
+    };
+    let spans: Vec<(Range<usize>, ColorIntegerSize)> =
+        line.sung.iter().map(|r| (r.clone(), SUNG_COLOR)).collect();
+    general
+        .ui
+        .write_spans(&id, left + x, row, &text, SUBTITLE_COLOR, &spans);
+}
+
+/// Smallest size the layout is worked out for. Terminals smaller than this get clipped by curses.
+pub const MIN_WIDTH: usize = 40;
+pub const MIN_HEIGHT: usize = 10;
+/// Rows that aren't the song list: top border, subtitle separator, 3 footer rows, bottom border.
+const CHROME_ROWS: usize = 6;
+/// Rows the song list keeps when the subtitle pane grows.
+const MIN_SONG_ROWS: usize = 2;
+
+/// Rows the subtitle pane takes from the song list, only while a song has subtitles.
+fn pane_rows(general: &GeneralState) -> usize {
+    if general.subtitle.is_none() {
+        return 0;
+    }
+    let free = (general.ui.height() - CHROME_ROWS).saturating_sub(MIN_SONG_ROWS);
+    general.config.subtitle_lines.saturating_sub(1).min(free)
+}
+
+fn songs_region(general: &GeneralState) -> ((usize, usize), (usize, usize)) {
+    let (w, h) = (general.ui.width(), general.ui.height());
+    ((2, w - 4), (1, h - CHROME_ROWS - pane_rows(general)))
 }
 
+/// Every region is worked out from the UI size. The footer keeps its 50 column look,
+/// extra width goes to the song list, playlist, sliding title, artist and progress bar.
 pub fn autoalloc(general: &mut GeneralState) {
+    let (w, h) = (general.ui.width(), general.ui.height());
+    let (songs_x, songs_y) = songs_region(general);
+    let separator = h - 5;
+    let (row1, row2, row3) = (h - 4, h - 3, h - 2);
+    let playlist_w = (w - 14) / 3;
     /* ---------------- BEGIN ALLOCATION ---------------- */
-    general.ui.alloc(&Ownership::Songs, (2, 46), (1, 14));
-    general.ui.alloc(&Ownership::SongInd, (1, 1), (1, 14));
-    general.ui.alloc(&Ownership::Playlist, (2, 12), (16, 1));
-    general.ui.alloc(&Ownership::Sliding, (14, 23), (16, 1));
-    general.ui.alloc(&Ownership::ShuRep, (2, 8), (17, 1));
-    general.ui.alloc(&Ownership::Time1, (12, 5), (17, 1));
-    general.ui.alloc(&Ownership::Time2, (34, 5), (17, 1));
-    general.ui.alloc(&Ownership::ShuInd, (2, 3), (18, 1));
-    general.ui.alloc(&Ownership::LoopInd, (6, 3), (18, 1));
-    general.ui.alloc(&Ownership::Artist, (12, 27), (18, 1));
-    general.ui.alloc(&Ownership::RpcVol, (41, 7), (17, 1));
-    general.ui.alloc(&Ownership::RpcInd, (41, 3), (18, 1));
-    general.ui.alloc(&Ownership::VolInd, (45, 3), (18, 1));
+    general.ui.alloc(&Ownership::Songs, songs_x, songs_y);
+    general.ui.alloc(&Ownership::SongInd, (1, 1), songs_y);
+    general
+        .ui
+        .alloc(&Ownership::Playlist, (2, playlist_w), (row1, 1));
+    general.ui.alloc(
+        &Ownership::Sliding,
+        (2 + playlist_w, w - 22 - playlist_w),
+        (row1, 1),
+    );
+    general.ui.alloc(&Ownership::ShuRep, (2, 8), (row2, 1));
+    general.ui.alloc(&Ownership::Time1, (12, 5), (row2, 1));
+    general.ui.alloc(&Ownership::Time2, (w - 16, 5), (row2, 1));
+    general.ui.alloc(&Ownership::ShuInd, (2, 3), (row3, 1));
+    general.ui.alloc(&Ownership::LoopInd, (6, 3), (row3, 1));
     general
         .ui
-        .c_alloc(&Ownership::Subtitle, (2, 46), (15, 1), Some("─".to_string()));
+        .alloc(&Ownership::Artist, (12, w - 23), (row3, 1));
+    general.ui.alloc(&Ownership::RpcVol, (w - 9, 7), (row2, 1));
+    general.ui.alloc(&Ownership::RpcInd, (w - 9, 3), (row3, 1));
+    general.ui.alloc(&Ownership::VolInd, (w - 5, 3), (row3, 1));
+    general.ui.alloc(&Ownership::GainInd, (w - 9, 7), (row1, 1));
+    general.ui.alloc(&Ownership::CrossInd, (w - 17, 6), (row1, 1));
+    general
+        .ui
+        .c_alloc(&Ownership::Subtitle, (2, w - 4), (separator, 1), Some("─".to_string()));
+    let pane = pane_rows(general);
+    if pane > 0 {
+        general
+            .ui
+            .alloc(&Ownership::SubtitlePane, (1, w - 2), (separator - pane, pane));
+    }
     general
         .ui
-        .c_alloc(&Ownership::Search, (2, 32), (0, 1), Some("─".to_string()));
+        .c_alloc(&Ownership::Search, (2, w - 18), (0, 1), Some("─".to_string()));
     general
         .ui
-        .c_alloc(&Ownership::Page, (35, 13), (0, 1), Some("─".to_string()));
+        .c_alloc(&Ownership::Page, (w - 15, 13), (0, 1), Some("─".to_string()));
     general.ui.c_alloc(
         &Ownership::Progress,
-        (18, 15),
-        (17, 1),
+        (18, w - 35),
+        (row2, 1),
         Some("─".to_string()),
     );
     /* ---------------- END ALLOCATION ---------------- */
 }
 
+/// Sizes the UI after the terminal and lays everything out again.
+/// Everything has to be redrawn afterwards.
+pub fn relayout(general: &mut GeneralState, window: &Window) {
+    let (h, w) = window.get_max_yx();
+    general.ui.resize(
+        (w.max(0) as usize).max(MIN_WIDTH),
+        (h.max(0) as usize).max(MIN_HEIGHT),
+    );
+    fit_layout(general);
+}
+
+fn fit_layout(general: &mut GeneralState) {
+    autoalloc(general);
+    general.wrap_lyrics();
+    // page size follows the rows, keep the cursor on the same song
+    let absolute = general.cursor().min(general.row_count().saturating_sub(1));
+    general.songs.typical_page_size = songs_region(general).1.1;
+    general.set_cursor(absolute);
+    let sliding = general.ui.get_range(&Ownership::Sliding).unwrap_or(23);
+    general.sliding.set_width(sliding);
+}
+
+/// Lays out and redraws everything when the subtitle pane has to grow or shrink, after
+/// subtitles were loaded or dropped.
+pub fn fit_subtitle_pane(general: &mut GeneralState, page: &mut PageData) {
+    if general.songs.typical_page_size == songs_region(general).1.1 {
+        return;
+    }
+    fit_layout(general);
+    *page = PageData::new();
+    draw_all(general, page);
+    draw_subtitle(general, None);
+}
+
+/// Keeps the cursor on an existing row after the list shrank.
+pub fn clamp_cursor(general: &mut GeneralState) {
+    let total = general.row_count();
+    let psize = general.songs.typical_page_size.max(1);
+    let pages = total.div_ceil(psize).max(1);
+    general.index.page = general.index.page.clamp(1, pages);
+    let rows = total.saturating_sub((general.index.page - 1) * psize).min(psize);
+    general.index.index = general.index.index.min(rows.saturating_sub(1));
+}
+
 pub fn draw_frame(general: &mut GeneralState) {
+    let (w, h) = (general.ui.width(), general.ui.height());
+    let separator = h - 5;
     general.ui.inject_si(0, 0, "┌", 0);
-    general.ui.inject_si(49, 0, "┐", 0);
-    general.ui.inject_si(0, 19, "└", 0);
-    general.ui.inject_si(49, 19, "┘", 0);
-    general.ui.inject_simx(1, 0, "──────", 0, 8);
-    general.ui.inject_simx(1, 15, "──────", 0, 8);
-    general.ui.inject_simx(1, 19, "──────", 0, 8);
-    general.ui.inject_simy(0, 1, "│", 0, 18);
-    general.ui.inject_simy(49, 1, "│", 0, 18);
-    general.ui.inject_si(0, 15, "├", 0);
-    general.ui.inject_si(49, 15, "┤", 0);
+    general.ui.inject_si(w - 1, 0, "┐", 0);
+    general.ui.inject_si(0, h - 1, "└", 0);
+    general.ui.inject_si(w - 1, h - 1, "┘", 0);
+    general.ui.inject_simx(1, 0, "─", 0, w - 2);
+    general.ui.inject_simx(1, separator, "─", 0, w - 2);
+    general.ui.inject_simx(1, h - 1, "─", 0, w - 2);
+    general.ui.inject_simy(0, 1, "│", 0, h - 2);
+    general.ui.inject_simy(w - 1, 1, "│", 0, h - 2);
+    general.ui.inject_si(0, separator, "├", 0);
+    general.ui.inject_si(w - 1, separator, "┤", 0);
 }
 
 pub fn draw_page(general: &mut GeneralState) {
-    let total = general.songs.filtered_songs.len();
+    let total = general.row_count();
     let psize = general.songs.typical_page_size.max(1);
     let max_page = (total + psize - 1) / psize;
     let cur_page = general.index.page.max(1).min(max_page.max(1));
@@ -120,18 +277,38 @@ pub fn draw_page(general: &mut GeneralState) {
 }
 
 pub fn draw_search(general: &mut GeneralState) {
-    let _x = format!("Search: {}", general.searchquery.query);
-    general.ui.write(
-        &Ownership::Search,
-        0,
-        0,
-        if general.searchquery.mode == 0 {
-            "Search or edit"
-        } else {
-            &_x
+    let query = &general.searchquery.query;
+    let _x = match (general.searchquery.mode, general.view) {
+        (0, View::Songs) => match &general.songs.source {
+            Some(source) => format!("Playlist: {}", source.name),
+            None => "Search or edit".to_string(),
         },
-        9,
-    );
+        (0, View::Queue) => format!("Queue: {} songs", general.songs.queue.len()),
+        (0, View::History) => "Recently played".to_string(),
+        (0, View::Playlists) => format!("Playlists: {}", general.playlists.lists.len()),
+        (0, View::Lyrics) if general.lyrics.is_empty() => match general.subtitle {
+            Some(_) => "Lyrics: synced, on the subtitle line".to_string(),
+            None => "Lyrics: none".to_string(),
+        },
+        (0, View::Lyrics) => format!("Lyrics: {}", general.songs.current_name()),
+        (4, _) => format!("Add to playlist: {}", query),
+        (5, _) => format!("New playlist: {}", query),
+        (6, _) => format!("Rename to: {}", query),
+        (7, _) => {
+            let name = general
+                .playlists
+                .get(general.cursor())
+                .map_or("", |l| l.name.as_str());
+            format!("Delete {}? y/n: {}", name, query)
+        }
+        (8, _) => format!("Import file: {}", query),
+        (9, _) => format!("Export to: {}", query),
+        _ => match &general.searchquery.error {
+            Some(error) => format!("Search: {} <- {}", query, error),
+            None => format!("Search: {}", query),
+        },
+    };
+    general.ui.write(&Ownership::Search, 0, 0, &_x, 9);
 }
 
 pub fn draw_header(general: &mut GeneralState) {
@@ -139,10 +316,70 @@ pub fn draw_header(general: &mut GeneralState) {
     draw_page(general);
 }
 
+/// A row as the list shows it, rows being what general.rows() returned. The history view
+/// puts the time a song started in front, the playlist browser lists playlists and the
+/// lyrics view lines of lyrics.
+fn row_label(general: &GeneralState, rows: &[SongId], abs: usize) -> String {
+    match general.view {
+        View::Playlists => {
+            return match general.playlists.get(abs) {
+                Some(list) => format!("{} ({})", list.name, list.paths.len()),
+                None => String::new(),
+            };
+        }
+        View::Lyrics => return general.lyrics.get(abs).cloned().unwrap_or_default(),
+        _ => {}
+    }
+    let songs = &general.songs;
+    let name = rows
+        .get(abs)
+        .and_then(|&id| songs.song(id))
+        .map_or("", |s| s.display_name(songs.tag_titles));
+    match general.view {
+        View::History => match general.songs.history.iter().rev().nth(abs) {
+            Some(&(_, started)) => {
+                let started = DateTime::<Local>::from(started);
+                format!("{}  {}", started.format("%H:%M"), name)
+            }
+            None => name.to_string(),
+        },
+        _ => name.to_string(),
+    }
+}
+
+/// Draws a row of the song list, with what the search matched in the song list in
+/// another color.
+fn write_row(general: &mut GeneralState, row: usize, label: &str, selected: bool) {
+    let width = general.ui.get_range(&Ownership::Songs).unwrap_or(0);
+    let fitted = fit_width(label, width);
+    let (color, match_color) = if selected { (3, 5) } else { (0, 4) };
+    let mut spans: Vec<(Range<usize>, ColorIntegerSize)> = Vec::new();
+    if general.view == View::Songs {
+        // fit_width keeps a prefix of the label and adds … if it had to cut
+        let kept = match fitted.len() == label.len() {
+            true => fitted.chars().count(),
+            false => fitted.chars().count().saturating_sub(1),
+        };
+        let chars: Vec<(usize, char)> = fitted.char_indices().collect();
+        for pos in general.songs.highlights(label) {
+            let Some(&(start, c)) = chars.get(pos).filter(|_| pos < kept) else {
+                continue;
+            };
+            match spans.last_mut() {
+                Some((span, _)) if span.end == start => span.end += c.len_utf8(),
+                _ => spans.push((start..start + c.len_utf8(), match_color)),
+            }
+        }
+    }
+    general
+        .ui
+        .write_spans(&Ownership::Songs, 0, row, &fitted, color, &spans);
+}
+
 pub struct PageData {
     current: Option<usize>,
     next: Option<usize>,
-    blacklist: Vec<usize>,
+    marked: Vec<usize>,
     select: usize,
 }
 
@@ -151,15 +388,13 @@ impl PageData {
         Self {
             current: None,
             next: None,
-            blacklist: Vec::with_capacity(3),
+            marked: Vec::with_capacity(3),
             select: 0,
         }
     }
     fn get_name<'life>(&self, general: &'life mut GeneralState, idx: usize) -> String {
-        general.songs.all_songs[general.songs.get_ordered()
-            [(general.index.page.max(1) - 1) * general.songs.typical_page_size.max(1) + idx]]
-            .name
-            .clone()
+        let abs = (general.index.page.max(1) - 1) * general.songs.typical_page_size.max(1) + idx;
+        row_label(general, &general.rows(), abs)
     }
     pub fn draw_unchanged_moved_page(&mut self, general: &mut GeneralState) {
         if self.select == general.index.index {
@@ -167,23 +402,14 @@ impl PageData {
         }
         let name = &self.get_name(general, self.select);
         if !general.state.desel {
-            general.ui.write(&Ownership::Songs, 0, self.select, name, 0);
-            general.ui.write(
-                &Ownership::Songs,
-                0,
-                general.index.index,
-                &general.songs.all_songs[general.songs.get_ordered()[(general.index.page.max(1)
-                    - 1)
-                    * general.songs.typical_page_size.max(1)
-                    + general.index.index]]
-                    .name,
-                3,
-            );
+            write_row(general, self.select, name, false);
+            let selected = self.get_name(general, general.index.index);
+            write_row(general, general.index.index, &selected, true);
         }
         self.select = general.index.index;
     }
     pub fn draw_changed_moved_page(&mut self, general: &mut GeneralState) {
-        let total = general.songs.filtered_songs.len();
+        let total = general.row_count();
         let psize = general.songs.typical_page_size.max(1);
 
         let page = general.index.page.max(1);
@@ -192,23 +418,14 @@ impl PageData {
 
         let mut row = 0;
 
-        let g = general.songs.get_ordered();
+        let g = general.rows();
         for abs in start..end {
-            let original = g[abs];
-            let song = &general.songs.all_songs[original];
-
-            general.ui.write(
-                &Ownership::Songs,
-                0,
-                row,
-                &song.name,
-                if general.index.index == row && !general.state.desel {
-                    self.select = row;
-                    3
-                } else {
-                    0
-                },
-            );
+            let name = row_label(general, &g, abs);
+            let selected = general.index.index == row && !general.state.desel;
+            if selected {
+                self.select = row;
+            }
+            write_row(general, row, &name, selected);
 
             row += 1;
         }
@@ -226,36 +443,55 @@ impl PageData {
         if let Some(r) = self.next {
             general.ui.empty_instruction(&Ownership::SongInd, r);
         }
-        for &r in &self.blacklist {
+        for &r in &self.marked {
             general.ui.empty_instruction(&Ownership::SongInd, r);
         }
         self.current = None;
         self.next = None;
-        self.blacklist.clear();
+        self.marked.clear();
+        if general.view == View::Lyrics {
+            return;
+        }
 
-        let total = general.songs.filtered_songs.len();
+        let total = general.row_count();
         let psize = general.songs.typical_page_size.max(1);
 
         let start = (general.index.page.max(1) - 1) * psize;
 
-        let current = general.songs.match_c();
+        let current = general.songs.current;
         let next = general.songs.get_next();
 
+        if general.view == View::Playlists {
+            let source = general.songs.source.as_ref().map(|s| s.name.clone());
+            for abs in start..(start + psize).min(total) {
+                if general.playlists.get(abs).map(|l| &l.name) == source.as_ref() {
+                    self.current = Some(abs - start);
+                    general
+                        .ui
+                        .write(&Ownership::SongInd, 0, abs - start, ">", 1);
+                }
+            }
+            return;
+        }
+
         let mut row = 0;
 
-        let g = general.songs.get_ordered();
+        let g = general.rows();
         for abs in start..(start + psize).min(total) {
-            let original = g[abs];
+            let id = g[abs];
 
-            if abs == current {
+            if Some(id) == current {
                 self.current = Some(row);
                 general.ui.write(&Ownership::SongInd, 0, row, ">", 1);
-            } else if original == next && !general.state.isloop {
+            } else if Some(id) == next && general.songs.repeat != RepeatMode::One {
                 self.next = Some(row);
                 general.ui.write(&Ownership::SongInd, 0, row, "*", 4);
-            } else if general.songs.is_blacklist(original) {
-                self.blacklist.push(row);
+            } else if general.songs.is_blacklist(id) {
+                self.marked.push(row);
                 general.ui.write(&Ownership::SongInd, 0, row, "x", 2);
+            } else if general.view != View::Queue && general.songs.is_queued(id) {
+                self.marked.push(row);
+                general.ui.write(&Ownership::SongInd, 0, row, "+", 9);
             }
 
             row += 1;
@@ -265,13 +501,11 @@ impl PageData {
 
 pub fn draw_playlist(general: &mut GeneralState) {
     general.ui.empty_instruction(&Ownership::Playlist, 0);
-    general.ui.write(
-        &Ownership::Playlist,
-        0,
-        0,
-        &general.songs.current_playlist(),
-        0,
+    let playlist = fit_width(
+        &general.songs.current_album(),
+        general.ui.get_range(&Ownership::Playlist).unwrap_or(0),
     );
+    general.ui.write(&Ownership::Playlist, 0, 0, &playlist, 0);
 }
 pub fn draw_sliding(general: &mut GeneralState) {
     let sliding = general.sliding.visible_text();
@@ -291,21 +525,23 @@ pub fn draw_const(general: &mut GeneralState) {
 }
 
 pub fn draw_shuffle_indc(general: &mut GeneralState) {
+    let mode = general.songs.shuffle;
     general.ui.write(
         &Ownership::ShuInd,
         0,
         0,
-        if general.songs.shuffle { "yes" } else { "no" }.into(),
-        if general.songs.shuffle { 1 } else { 2 },
+        mode.label(),
+        if mode == ShuffleMode::Off { 2 } else { 1 },
     );
 }
 pub fn draw_loop_indc(general: &mut GeneralState) {
+    let mode = general.songs.repeat;
     general.ui.write(
         &Ownership::LoopInd,
         0,
         0,
-        if general.state.isloop { "yes" } else { "no" }.into(),
-        if general.state.isloop { 1 } else { 2 },
+        mode.label(),
+        if mode == RepeatMode::Off { 2 } else { 1 },
     );
 }
 pub fn draw_time_cur(general: &mut GeneralState) {
@@ -323,7 +559,10 @@ pub fn draw_time_max(general: &mut GeneralState) {
         .write(&Ownership::Time2, 0, 0, &to_mm_ss(general.timer.maxlen), 0);
 }
 pub fn draw_artist(general: &mut GeneralState) {
-    let artist = general.songs.current_artist();
+    let artist = fit_width(
+        &general.songs.current_artist(),
+        general.ui.get_range(&Ownership::Artist).unwrap_or(0),
+    );
     general.ui.write(
         &Ownership::Artist,
         (general.ui.get_range(&Ownership::Artist).unwrap() / 2).saturating_sub(artist.width() / 2),
@@ -368,6 +607,32 @@ pub fn draw_vol_indc(general: &mut GeneralState) {
     );
 }
 
+pub fn draw_gain_indc(general: &mut GeneralState) {
+    let mode = general.state.gain_mode;
+    general.ui.write(
+        &Ownership::GainInd,
+        0,
+        0,
+        &format!("RG  {}", mode.label()),
+        if mode == GainMode::Off { 2 } else { 1 },
+    );
+}
+
+pub fn draw_crossfade_indc(general: &mut GeneralState) {
+    let crossfade = general.gapless.crossfade;
+    let length = match crossfade.as_secs() {
+        0 => "off".to_string(),
+        secs => format!("{}s", secs),
+    };
+    general.ui.write(
+        &Ownership::CrossInd,
+        0,
+        0,
+        &format!("XF {:>3}", length),
+        if crossfade.is_zero() { 2 } else { 1 },
+    );
+}
+
 pub fn draw_footer(general: &mut GeneralState) {
     draw_playlist(general);
     draw_sliding(general);
@@ -379,6 +644,8 @@ pub fn draw_footer(general: &mut GeneralState) {
     draw_artist(general);
     draw_rpc_indc(general);
     draw_vol_indc(general);
+    draw_gain_indc(general);
+    draw_crossfade_indc(general);
 }
 
 pub fn draw_progress(general: &mut GeneralState) {
@@ -388,7 +655,11 @@ pub fn draw_progress(general: &mut GeneralState) {
         0,
         "─",
         1,
-        calc(general.timer.maxlen, general.timer.fcalc),
+        calc(
+            general.timer.maxlen,
+            general.timer.fcalc,
+            general.ui.get_range(&Ownership::Progress).unwrap_or(15),
+        ),
     );
 }
 
@@ -414,7 +685,6 @@ pub fn init_curses(window: &mut Window) {
         window.nodelay(true),
         mousemask(0x2 as u32, None),
     );
-    window.resize(20, 50);
     (
         pancurses::start_color(),
         pancurses::init_pair(0, pancurses::COLOR_WHITE, pancurses::COLOR_BLACK),
@@ -422,6 +692,8 @@ pub fn init_curses(window: &mut Window) {
         pancurses::init_pair(2, pancurses::COLOR_RED, pancurses::COLOR_BLACK),
         pancurses::init_pair(3, pancurses::COLOR_BLACK, pancurses::COLOR_WHITE),
         pancurses::init_pair(4, pancurses::COLOR_YELLOW, pancurses::COLOR_BLACK),
+        // search matches on the selected row, 4 on the others
+        pancurses::init_pair(5, pancurses::COLOR_RED, pancurses::COLOR_WHITE),
         pancurses::init_pair(9, pancurses::COLOR_CYAN, pancurses::COLOR_BLACK),
     );
 }
@@ -13,22 +13,83 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex, mpsc::Sender};
 use std::thread;
 
+use std::time::Duration;
+
 use zbus::blocking::Connection;
 use zbus::interface;
-use zvariant::Value;
+use zvariant::{ObjectPath, Value};
 
 use crate::modules::general::Action;
+use crate::modules::songs::{RepeatMode, Song, SongId};
 
 const BUS_NAME: &str = "org.mpris.MediaPlayer2.neocrystal";
 const OBJ_PATH: &str = "/org/mpris/MediaPlayer2";
+pub const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+fn loop_status(mode: RepeatMode) -> &'static str {
+    match mode {
+        RepeatMode::Off => "None",
+        RepeatMode::One => "Track",
+        RepeatMode::All => "Playlist",
+    }
+}
+
+fn metadata(s: &MprisState) -> HashMap<String, Value<'static>> {
+    let mut m = HashMap::new();
+    m.insert("xesam:title".into(), Value::new(s.title.clone()));
+    m.insert("xesam:artist".into(), Value::new(s.artist.clone()));
+    m.insert("mpris:length".into(), Value::new(s.length_us));
+    if let Ok(path) = ObjectPath::try_from(track_id(s.track)) {
+        m.insert("mpris:trackid".into(), Value::new(path));
+    }
+    let Some(song) = &s.song else {
+        return m;
+    };
+    if !song.album.is_empty() {
+        m.insert("xesam:album".into(), Value::new(song.album.clone()));
+    }
+    let lists = [
+        ("xesam:albumArtist", &song.album_artist),
+        ("xesam:genre", &song.genre),
+        ("xesam:composer", &song.composer),
+        ("xesam:comment", &song.comment),
+    ];
+    for (key, field) in lists {
+        if let Some(field) = field {
+            m.insert(key.into(), Value::new(vec![field.clone()]));
+        }
+    }
+    if let Some(track) = song.track {
+        m.insert("xesam:trackNumber".into(), Value::new(track as i32));
+    }
+    if let Some(disc) = song.disc {
+        m.insert("xesam:discNumber".into(), Value::new(disc as i32));
+    }
+    m
+}
+
+pub fn track_id(song: Option<SongId>) -> String {
+    match song {
+        Some(id) => format!("/org/neocrystal/track/{}", id),
+        None => NO_TRACK.into(),
+    }
+}
+
+enum Emit {
+    Properties,
+    Seeked(i64),
+}
 
 pub struct MprisHandle {
     pub state: Arc<Mutex<MprisState>>,
-    emit_tx: Sender<()>,
+    emit_tx: Sender<Emit>,
 }
 impl MprisHandle {
     pub fn emit(&self) {
-        let _ = self.emit_tx.send(());
+        let _ = self.emit_tx.send(Emit::Properties);
+    }
+    pub fn seeked(&self, position_us: i64) {
+        let _ = self.emit_tx.send(Emit::Seeked(position_us));
     }
 }
 
@@ -38,6 +99,11 @@ pub struct MprisState {
     pub title: String,
     pub artist: Vec<String>,
     pub length_us: i64,
+    pub position_us: i64,
+    pub track: Option<SongId>,
+    /// Where the album and the other tags come from
+    pub song: Option<Song>,
+    pub repeat: RepeatMode,
 }
 
 impl Default for MprisState {
@@ -47,6 +113,10 @@ impl Default for MprisState {
             title: "Nothing".into(),
             artist: vec!["Nothing".into()],
             length_us: 0,
+            position_us: 0,
+            track: None,
+            song: None,
+            repeat: RepeatMode::All,
         }
     }
 }
@@ -96,7 +166,7 @@ struct MprisPlayer {
 #[interface(name = "org.mpris.MediaPlayer2.Player")]
 impl MprisPlayer {
     fn play(&self) {
-        let _ = self.tx.send(Action::Play(1, 0));
+        let _ = self.tx.send(Action::Play(None));
     }
 
     fn pause(&self) {
@@ -108,7 +178,7 @@ impl MprisPlayer {
         let _ = match s {
             0 => self.tx.send(Action::Stop),
             1 => self.tx.send(Action::Resume),
-            _ => self.tx.send(Action::Play(1, 0)),
+            _ => self.tx.send(Action::Play(None)),
         };
     }
 
@@ -120,6 +190,27 @@ impl MprisPlayer {
         let _ = self.tx.send(Action::DbusPrev);
     }
 
+    fn seek(&self, offset: i64) {
+        let s = self.state.lock().unwrap();
+        let target = s.position_us.saturating_add(offset).max(0);
+        // spec: seeking past the end behaves like Next
+        let _ = if target > s.length_us {
+            self.tx.send(Action::DbusNext)
+        } else {
+            self.tx.send(Action::SeekTo(Duration::from_micros(target as u64)))
+        };
+    }
+
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        let s = self.state.lock().unwrap();
+        if track_id.as_str() != self::track_id(s.track) || position < 0 || position > s.length_us {
+            return;
+        }
+        let _ = self
+            .tx
+            .send(Action::SeekTo(Duration::from_micros(position as u64)));
+    }
+
     #[zbus(property)]
     fn playback_status(&self) -> String {
         match self.state.lock().unwrap().playback_status {
@@ -132,12 +223,28 @@ impl MprisPlayer {
 
     #[zbus(property)]
     fn metadata(&self) -> HashMap<String, Value<'_>> {
-        let s = self.state.lock().unwrap();
-        let mut m = HashMap::new();
-        m.insert("xesam:title".into(), Value::new(s.title.clone()));
-        m.insert("xesam:artist".into(), Value::new(s.artist.clone()));
-        m.insert("mpris:length".into(), Value::new(s.length_us));
-        m
+        metadata(&self.state.lock().unwrap())
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> String {
+        loop_status(self.state.lock().unwrap().repeat).into()
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&self, status: String) {
+        let mode = match status.as_str() {
+            "None" => RepeatMode::Off,
+            "Track" => RepeatMode::One,
+            "Playlist" => RepeatMode::All,
+            _ => return,
+        };
+        let _ = self.tx.send(Action::SetRepeat(mode));
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.state.lock().unwrap().position_us
     }
 
     #[zbus(property)]
@@ -156,13 +263,17 @@ impl MprisPlayer {
     fn can_go_previous(&self) -> bool {
         true
     }
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
 }
 
 pub fn spawn_mpris(action_tx: Sender<Action>) -> MprisHandle {
     let state = Arc::new(Mutex::new(MprisState::default()));
     let state_clone = state.clone();
 
-    let (emit_tx, emit_rx) = std::sync::mpsc::channel::<()>();
+    let (emit_tx, emit_rx) = std::sync::mpsc::channel::<Emit>();
 
     thread::spawn(move || {
         let conn = Connection::session().expect("D-Bus session failed");
@@ -181,7 +292,16 @@ pub fn spawn_mpris(action_tx: Sender<Action>) -> MprisHandle {
             .unwrap();
 
         loop {
-            emit_rx.recv().unwrap();
+            if let Emit::Seeked(position) = emit_rx.recv().unwrap() {
+                let _ = conn.emit_signal(
+                    None::<&str>,
+                    OBJ_PATH,
+                    "org.mpris.MediaPlayer2.Player",
+                    "Seeked",
+                    &(position,),
+                );
+                continue;
+            }
 
             let s = state_clone.lock().unwrap();
 
@@ -196,12 +316,8 @@ pub fn spawn_mpris(action_tx: Sender<Action>) -> MprisHandle {
                 }),
             );
 
-            let mut metadata = HashMap::<&str, Value>::new();
-            metadata.insert("xesam:title", Value::new(s.title.clone()));
-            metadata.insert("xesam:artist", Value::new(s.artist.clone()));
-            metadata.insert("mpris:length", Value::new(s.length_us));
-
-            changed.insert("Metadata", Value::new(metadata));
+            changed.insert("Metadata", Value::new(metadata(&s)));
+            changed.insert("LoopStatus", Value::new(loop_status(s.repeat)));
 
             let _ = conn.emit_signal(
                 None::<&str>,
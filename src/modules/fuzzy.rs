@@ -0,0 +1,143 @@
+// fzf style fuzzy matching: the pattern's characters have to show up in order, and
+// matches that are close together or start words score higher.
+// Both sides are folded first, so "beyonce" finds "Beyoncé" and "motley" finds "Mötley".
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+const MATCH: i64 = 16;
+const GAP_START: i64 = 3;
+const GAP_EXTENSION: i64 = 1;
+/// After a space, a separator or at the start.
+const BONUS_BOUNDARY: i64 = 8;
+/// fooBar and foo2.
+const BONUS_CAMEL: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+/// The first pattern character counts its bonus this many times.
+const FIRST_MULTIPLIER: i64 = 2;
+
+/// Lowercase without accents, always one char for one so positions carry over.
+pub fn fold(c: char) -> char {
+    if c.is_ascii() {
+        return c.to_ascii_lowercase();
+    }
+    let base = c.nfd().find(|&d| !is_combining_mark(d)).unwrap_or(c);
+    base.to_lowercase().next().unwrap_or(base)
+}
+
+pub fn fold_str(text: &str) -> String {
+    text.chars().map(fold).collect()
+}
+
+fn bonus(prev: Option<char>, c: char) -> i64 {
+    match prev {
+        None => BONUS_BOUNDARY,
+        Some(p) if !p.is_alphanumeric() && c.is_alphanumeric() => BONUS_BOUNDARY,
+        Some(p) if p.is_lowercase() && c.is_uppercase() => BONUS_CAMEL,
+        Some(p) if !p.is_numeric() && c.is_numeric() => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+/// Scores text against a folded pattern and returns the char positions that matched,
+/// None if the pattern isn't a subsequence of the text.
+pub fn find(pattern: &[char], text: &str) -> Option<(i64, Vec<usize>)> {
+    let chars: Vec<char> = text.chars().collect();
+    let folded: Vec<char> = chars.iter().copied().map(fold).collect();
+    let (m, n) = (pattern.len(), folded.len());
+    if m == 0 {
+        return Some((0, Vec::new()));
+    }
+    // quick check before the table
+    let mut rest = folded.iter();
+    if !pattern.iter().all(|p| rest.any(|c| c == p)) {
+        return None;
+    }
+    let bonuses: Vec<i64> = (0..n)
+        .map(|j| bonus(j.checked_sub(1).map(|k| chars[k]), chars[j]))
+        .collect();
+
+    // score[i][j]: best score with pattern[i] matched at text[j], from[i][j]: where
+    // pattern[i - 1] was matched for it, run[i][j]: the bonus of the first character of
+    // the consecutive run it ends, which the whole run gets
+    let mut score = vec![vec![None::<i64>; n]; m];
+    let mut from = vec![vec![0usize; n]; m];
+    let mut run = vec![vec![0i64; n]; m];
+    for j in 0..n {
+        if folded[j] == pattern[0] {
+            score[0][j] = Some(MATCH + bonuses[j] * FIRST_MULTIPLIER);
+            run[0][j] = bonuses[j];
+        }
+    }
+    for i in 1..m {
+        // best score[i - 1][k] for k < j - 1 counting what the gap from k costs, which
+        // grows the same for every k as j moves on
+        let mut gap: Option<(i64, usize)> = None;
+        for j in 1..n {
+            if j >= 2
+                && let Some(s) = score[i - 1][j - 2]
+                && gap.is_none_or(|(g, _)| s + (j - 2) as i64 * GAP_EXTENSION > g)
+            {
+                gap = Some((s + (j - 2) as i64 * GAP_EXTENSION, j - 2));
+            }
+            if folded[j] != pattern[i] {
+                continue;
+            }
+            let run_bonus = bonuses[j].max(run[i - 1][j - 1]).max(BONUS_CONSECUTIVE);
+            let consecutive = score[i - 1][j - 1].map(|s| (s + MATCH + run_bonus, j - 1));
+            let gapped = gap.map(|(g, k)| {
+                let gap_cost = GAP_START + GAP_EXTENSION * (j - k - 2) as i64;
+                (
+                    g - k as i64 * GAP_EXTENSION - gap_cost + MATCH + bonuses[j],
+                    k,
+                )
+            });
+            let best = match (consecutive, gapped) {
+                (Some(c), Some(g)) => Some(if c.0 >= g.0 { c } else { g }),
+                (c, g) => c.or(g),
+            };
+            if let Some((s, k)) = best {
+                score[i][j] = Some(s);
+                from[i][j] = k;
+                run[i][j] = if k == j - 1 { run_bonus } else { bonuses[j] };
+            }
+        }
+    }
+    let (end, best) = (0..n)
+        .filter_map(|j| Some((j, score[m - 1][j]?)))
+        .max_by_key(|&(j, s)| (s, std::cmp::Reverse(j)))?;
+    let mut positions = vec![end; m];
+    for i in (1..m).rev() {
+        positions[i - 1] = from[i][positions[i]];
+    }
+    Some((best, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(p: &str) -> Vec<char> {
+        fold_str(p).chars().collect()
+    }
+
+    #[test]
+    fn folds_accents_and_case() {
+        assert_eq!(fold_str("Beyoncé MÖTLEY Ñ"), "beyonce motley n");
+        assert!(find(&pattern("beyonce"), "Beyoncé").is_some());
+        assert!(find(&pattern("bync"), "Beyoncé").is_some());
+        assert!(find(&pattern("abc"), "cab").is_none());
+    }
+
+    #[test]
+    fn word_starts_and_runs_score_higher() {
+        let score = |p: &str, t: &str| find(&pattern(p), t).unwrap().0;
+        assert!(score("dm", "Dire Moon") > score("dm", "adamant"));
+        assert!(score("moon", "Blue Moon") > score("moon", "mid october on"));
+        assert!(score("ac", "AbraCadabra") > score("ac", "abracadabra"));
+        let (_, positions) = find(&pattern("bm"), "Blue Moon").unwrap();
+        assert_eq!(positions, [0, 5]);
+        let (_, positions) = find(&pattern("moon"), "a moo Moon").unwrap();
+        assert_eq!(positions, [6, 7, 8, 9]);
+    }
+}
@@ -1,19 +1,27 @@
-use super::curses::Ownership;
+use super::curses::{MIN_HEIGHT, MIN_WIDTH, Ownership};
+use crate::modules::config::{Config, SubtitleLookup, expand_tilde};
+use crate::modules::loudness::GainMode;
+use crate::modules::lyrics::{Lyrics, read_embedded};
+use crate::modules::playlists::{self, Entry, Playlists};
 use crate::modules::presence::{
     RpcCommunication, rpc_init_autobuild, rpc_pretend_autobuild, rpc_rnw_autobuild,
 };
-use crate::modules::subtitle::PreciseSubtitleImport;
-use crate::modules::songs::absolute_index;
+use crate::modules::subtitle::{PreciseSubtitleImport, wrap_text};
+use crate::modules::songs::{RepeatMode, SongId, absolute_index};
 use crate::modules::tui_ir::{ColorIntegerSize, Execute};
 use crate::modules::utils::ReinitMode;
-use glob::glob;
-use home::home_dir;
 use pancurses::{COLOR_PAIR, Window};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
+/// Missing playlist entries named on the subtitle line after an import, the rest are
+/// printed on exit.
+const SHOWN_MISSING: usize = 3;
+
 use super::songs::Songs;
 use super::tui_ir::UI;
-use super::utils::{Indexer, RpcState, SearchQuery, SlidingText, State, Timer, Volume};
+use super::utils::{Gapless, Indexer, RpcState, SearchQuery, SlidingText, State, Timer, Volume};
 
 pub struct NcursesExec;
 
@@ -45,20 +53,281 @@ pub struct GeneralState {
     pub volume: Volume,
     pub ui: UI<Ownership>,
     pub subtitle: Option<PreciseSubtitleImport>,
+    /// Unsynced lyrics of the current song as the tags have them.
+    pub lyrics_text: Vec<String>,
+    /// The same wrapped to the list's width, the rows of the lyrics view.
+    pub lyrics: Vec<String>,
     pub action: Action,
     pub rpc: RpcState,
     pub sliding: SlidingText,
     pub searchquery: SearchQuery,
+    pub gapless: Gapless,
     pub songs: Songs,
+    pub playlists: Playlists,
+    pub config: Config,
+    pub view: View,
+    /// Cursors of the views that aren't shown, as positions in their rows.
+    pub cursors: HashMap<View, usize>,
+    /// Found while playing, like subtitles that didn't fully load.
+    pub problems: Vec<String>,
+    /// Sidecars whose problems are already in there, replaying a song doesn't repeat them.
+    pub reported: HashSet<PathBuf>,
 }
 
 impl GeneralState {
     pub fn blacklist(&mut self) {
-        self.songs.blacklist(absolute_index(
+        if let Some(id) = self.selected() {
+            self.songs.blacklist(id);
+        }
+    }
+
+    /// What the song list shows in the current view. The playlist browser lists
+    /// playlists, not songs, so it has none.
+    pub fn rows(&self) -> Vec<SongId> {
+        match self.view {
+            View::Songs => self.songs.get_ordered(),
+            View::Queue => self.songs.queue.iter().copied().collect(),
+            View::History => self.songs.history.iter().rev().map(|&(id, _)| id).collect(),
+            View::Playlists | View::Lyrics => Vec::new(),
+        }
+    }
+
+    pub fn row_count(&self) -> usize {
+        match self.view {
+            View::Songs => self.songs.filtered_songs.len(),
+            View::Queue => self.songs.queue.len(),
+            View::History => self.songs.history.len(),
+            View::Playlists => self.playlists.lists.len(),
+            View::Lyrics => self.lyrics.len(),
+        }
+    }
+
+    /// Position of the cursor in rows().
+    pub fn cursor(&self) -> usize {
+        absolute_index(
             self.index.index,
             self.index.page,
             self.songs.typical_page_size,
-        ));
+        )
+    }
+
+    pub fn set_cursor(&mut self, absolute: usize) {
+        let psize = self.songs.typical_page_size.max(1);
+        self.index.page = absolute / psize + 1;
+        self.index.index = absolute % psize;
+    }
+
+    /// The song under the cursor.
+    pub fn selected(&self) -> Option<SongId> {
+        self.rows().get(self.cursor()).copied()
+    }
+
+    /// Every view keeps its own cursor.
+    pub fn switch_view(&mut self, view: View) {
+        if self.view != view {
+            self.cursors.insert(self.view, self.cursor());
+            self.view = view;
+            self.set_cursor(self.cursors.get(&view).copied().unwrap_or(0));
+        }
+    }
+
+    /// Opens the view, or goes back to the song list if it's already open.
+    pub fn toggle_view(&mut self, view: View) {
+        self.switch_view(if self.view == view { View::Songs } else { view });
+    }
+
+    /// The playlist under the cursor in the browser.
+    fn selected_playlist(&self) -> Option<usize> {
+        (self.view == View::Playlists && self.cursor() < self.playlists.lists.len())
+            .then(|| self.cursor())
+    }
+
+    /// Makes the playlist under the cursor the list and moves to its top.
+    pub fn open_playlist(&mut self) -> bool {
+        let Some(index) = self.selected_playlist() else {
+            return false;
+        };
+        self.songs
+            .set_source(Some(self.playlists.lists[index].clone()));
+        self.switch_view(View::Songs);
+        self.set_cursor(0);
+        true
+    }
+
+    /// Back to the whole library.
+    pub fn close_playlist(&mut self) {
+        self.songs.set_source(None);
+        self.switch_view(View::Songs);
+        self.set_cursor(0);
+    }
+
+    /// Picks up changes to the playlist that is the list.
+    fn refresh_source(&mut self) {
+        let Some(name) = self.songs.source.as_ref().map(|s| s.name.clone()) else {
+            return;
+        };
+        match self.playlists.find(&name) {
+            Some(index) => self
+                .songs
+                .update_source(self.playlists.lists[index].clone()),
+            None => self.songs.set_source(None),
+        }
+    }
+
+    /// Adds the song under the cursor to the named playlist, which is made if needed.
+    /// The Ok and Err strings both say what happened.
+    pub fn add_to_playlist(&mut self, name: &str) -> Result<String, String> {
+        let Some(path) = self
+            .selected()
+            .and_then(|id| self.songs.song(id))
+            .map(|s| s.path.clone())
+        else {
+            return Err("no song selected".into());
+        };
+        let index = match self.playlists.find(name.trim()) {
+            Some(index) => index,
+            None => self.playlists.create(name, Vec::new())?,
+        };
+        let added = self.playlists.add(index, &path)?;
+        let name = &self.playlists.lists[index].name;
+        let message = match added {
+            true => format!("added to {}", name),
+            false => format!("already in {}", name),
+        };
+        self.refresh_source();
+        Ok(message)
+    }
+
+    pub fn new_playlist(&mut self, name: &str) -> Result<String, String> {
+        let index = self.playlists.create(name, Vec::new())?;
+        if self.view == View::Playlists {
+            self.set_cursor(index);
+        }
+        Ok(format!("made {}", self.playlists.lists[index].name))
+    }
+
+    pub fn rename_playlist(&mut self, name: &str) -> Result<String, String> {
+        let Some(index) = self.selected_playlist() else {
+            return Err("no playlist selected".into());
+        };
+        let old = self.playlists.lists[index].name.clone();
+        let index = self.playlists.rename(index, name)?;
+        let new = self.playlists.lists[index].name.clone();
+        if let Some(source) = self.songs.source.as_mut().filter(|s| s.name == old) {
+            source.name = new.clone();
+        }
+        self.refresh_source();
+        self.set_cursor(index);
+        Ok(format!("renamed {} to {}", old, new))
+    }
+
+    pub fn delete_playlist(&mut self) -> Result<String, String> {
+        let Some(index) = self.selected_playlist() else {
+            return Err("no playlist selected".into());
+        };
+        let gone = self.playlists.delete(index)?;
+        self.refresh_source();
+        Ok(format!("deleted {}", gone.name))
+    }
+
+    /// Takes the song under the cursor out of the playlist that is the list.
+    pub fn remove_from_playlist(&mut self) -> Result<String, String> {
+        let Some(name) = self.songs.source.as_ref().map(|s| s.name.clone()) else {
+            return Err("not in a playlist".into());
+        };
+        let Some(index) = self.playlists.find(&name) else {
+            return Err(format!("{} is gone", name));
+        };
+        let Some(path) = self
+            .selected()
+            .and_then(|id| self.songs.song(id))
+            .map(|s| s.path.clone())
+        else {
+            return Err("no song selected".into());
+        };
+        self.playlists.remove(index, &path)?;
+        self.refresh_source();
+        Ok(format!("removed from {}", name))
+    }
+
+    /// Reads a playlist file of another player into a new playlist named after it.
+    /// Returns the message and a line for every entry that isn't in the library.
+    pub fn import_playlist(&mut self, file: &str) -> Result<(String, Vec<String>), String> {
+        let file = expand_tilde(file.trim());
+        let path = Path::new(&file);
+        let entries = playlists::read_file(path)?;
+        let (paths, missing) = playlists::match_songs(&entries, &self.songs.all_songs);
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let index = self.playlists.create(&name, paths)?;
+        if self.view == View::Playlists {
+            self.set_cursor(index);
+        }
+        let mut message = format!(
+            "imported {}: {} songs, {} not found",
+            self.playlists.lists[index].name,
+            self.playlists.lists[index].paths.len(),
+            missing.len()
+        );
+        if !missing.is_empty() {
+            let shown: Vec<String> = missing
+                .iter()
+                .take(SHOWN_MISSING)
+                .map(|e| e.label())
+                .collect();
+            let more = if missing.len() > SHOWN_MISSING {
+                ", ..."
+            } else {
+                ""
+            };
+            message += &format!(": {}{}", shown.join(", "), more);
+        }
+        let missing = missing
+            .into_iter()
+            .map(|e| format!("{}: not in the library: {}", file, e.describe()))
+            .collect();
+        Ok((message, missing))
+    }
+
+    /// Writes what the view lists to a playlist file: the filtered song list, the queue,
+    /// or the playlist under the cursor in the browser. The extension picks the format.
+    pub fn export_list(&self, file: &str) -> Result<String, String> {
+        let file = expand_tilde(file.trim());
+        let entry = |id| self.songs.song(id).map(Entry::of_song);
+        let entries: Vec<Entry> = match self.view {
+            View::Songs => self
+                .songs
+                .get_ordered()
+                .into_iter()
+                .filter_map(entry)
+                .collect(),
+            View::Queue => self
+                .songs
+                .queue
+                .iter()
+                .filter_map(|&id| entry(id))
+                .collect(),
+            View::History | View::Lyrics => {
+                return Err("export the list, the queue or a playlist".into());
+            }
+            View::Playlists => {
+                let Some(index) = self.selected_playlist() else {
+                    return Err("no playlist selected".into());
+                };
+                // songs the library knows get their tags written too
+                self.playlists.lists[index]
+                    .paths
+                    .iter()
+                    .map(
+                        |path| match self.songs.all_songs.iter().find(|s| &s.path == path) {
+                            Some(song) => Entry::of_song(song),
+                            None => Entry::of_path(path),
+                        },
+                    )
+                    .collect()
+            }
+        };
+        playlists::write_file(Path::new(&file), &entries)?;
+        Ok(format!("exported {} songs to {}", entries.len(), file))
     }
 
     pub fn handle_rpc(&mut self, comm: &RpcCommunication, instant: Instant) {
@@ -81,66 +350,139 @@ impl GeneralState {
         self.rpc.reset();
     }
 
-    pub fn new() -> Self {
+    /// Wraps the lyrics to the width of the list, again whenever that changes.
+    pub fn wrap_lyrics(&mut self) {
+        let width = self.ui.get_range(&Ownership::Songs).unwrap_or(MIN_WIDTH);
+        self.lyrics = self
+            .lyrics_text
+            .iter()
+            .flat_map(|line| wrap_text(line, width))
+            .collect();
+    }
+
+    /// Loads the subtitle of the current song if the lookup policy finds one.
+    /// What's wrong with the file goes to the problems printed on exit, once per file.
+    /// Without a sidecar the lyrics in the song's tags are used, plain ones go to the
+    /// lyrics view.
+    pub fn load_subtitle(&mut self) {
+        self.subtitle = None;
+        self.lyrics_text.clear();
+        self.lyrics.clear();
+        if self.view == View::Lyrics {
+            self.set_cursor(0);
+        }
+        let song = self.songs.current_song_path();
+        if self.config.subtitle_lookup == SubtitleLookup::Off {
+            return;
+        }
+        let Some(path) = self.config.find_subtitle(&song) else {
+            match read_embedded(&song) {
+                Some(Lyrics::Synced(subtitles)) => {
+                    self.subtitle = Some(PreciseSubtitleImport::from_subtitles(subtitles))
+                }
+                Some(Lyrics::Text(lines)) => {
+                    self.lyrics_text = lines;
+                    self.wrap_lyrics();
+                }
+                None => {}
+            }
+            return;
+        };
+        let loaded = PreciseSubtitleImport::load(&path);
+        let report = self.reported.insert(path);
+        match loaded {
+            Ok((subtitle, mut problems)) => {
+                self.subtitle = Some(subtitle);
+                if report {
+                    self.problems.append(&mut problems);
+                }
+            }
+            Err(e) if report => self.problems.push(e),
+            Err(_) => {}
+        }
+    }
+
+    pub fn new(config: Config, playlists: Playlists) -> Self {
+        let mut songs = Songs::new();
+        songs.load_plays();
+        if let Some(seed) = config.seed {
+            songs.seed(seed);
+        }
+        songs.set_shuffle(config.shuffle);
+        songs.set_repeat(config.repeat);
+        songs.tag_titles = config.tag_titles;
         Self {
             index: Indexer { page: 1, index: 0 },
-            timer: Timer::new(),
+            timer: Timer {
+                seek_step: config.seek_step,
+                ..Timer::new()
+            },
             state: State {
                 spint: false,
-                isloop: false,
                 desel: false,
-                mouse_support: true,
+                mouse_support: config.mouse,
                 needs_update: true,
                 needs_dbus: true,
+                seeked: false,
+                gain_mode: GainMode::Off,
             },
             volume: Volume {
-                steps: 50,
-                step_div: 1,
+                steps: config.volume,
+                step_div: config.volume_step,
             },
-            ui: UI::new(50, 20),
+            ui: UI::new(MIN_WIDTH, MIN_HEIGHT),
             subtitle: None,
+            lyrics_text: Vec::new(),
+            lyrics: Vec::new(),
             action: Action::Nothing,
             rpc: RpcState {
                 reinit: false,
                 timer: Instant::now(),
                 mode: ReinitMode::None,
             },
-            sliding: SlidingText::new("Nothing", 23, Duration::from_millis(300)),
+            sliding: SlidingText::new("Nothing", 23, config.sliding_speed),
             searchquery: SearchQuery {
                 mode: 0,
                 query: String::from("false"),
+                error: None,
+            },
+            gapless: Gapless {
+                crossfade: config.crossfade,
+                ..Gapless::new()
             },
-            songs: Songs::constructor(globwrap()),
+            songs,
+            playlists,
+            config,
+            view: View::Songs,
+            cursors: HashMap::new(),
+            problems: Vec::new(),
+            reported: HashSet::new(),
         }
     }
 }
 
-fn home() -> String {
-    home_dir()
-        .expect("No home directory found")
-        .join("Music")
-        .to_string_lossy()
-        .to_string()
-}
-fn globwrap() -> Vec<String> {
-    let base = home();
-    ["*.mp3", "*.flac"]
-        .iter()
-        .flat_map(|ext| {
-            glob(&format!("{}/{}", base, ext))
-                .unwrap()
-                .filter_map(Result::ok)
-                .map(|p| p.display().to_string())
-        })
-        .collect()
+/// What the song list region shows.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum View {
+    Songs,
+    Queue,
+    History,
+    Playlists,
+    Lyrics,
 }
 
 #[allow(dead_code)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Action {
-    Play(usize, usize),
+    /// A clicked song, None plays the top of the list.
+    Play(Option<SongId>),
+    SeekTo(Duration),
     Shuffle,
     Repeat,
+    /// LoopStatus set over D-Bus.
+    SetRepeat(RepeatMode),
+    /// A clicked row in a view that doesn't list songs.
+    Pick(usize),
     Rpc,
     PgDown,
     PgUp,
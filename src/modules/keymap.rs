@@ -0,0 +1,420 @@
+// Keybindings. Defaults live here, ~/.config/neocrystal/keys.toml can override them:
+//
+//   [keys]
+//   play = "p"
+//   up = ["u", "Up"]
+//   quit = "C-q"
+//
+// Every action listed in the file replaces all of its default keys.
+// Key names: single characters, Space, Enter, Tab, Backspace, Esc, Up, Down, Left, Right,
+// PageUp, PageDown, Home, End, Insert, Delete, F1-F12, and C-x / A-x for Ctrl / Alt.
+
+use crate::modules::config::{Value, parse_toml};
+use home::home_dir;
+use pancurses::Input;
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum KeyAction {
+    Quit,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    SeekForward,
+    SeekBackward,
+    SeekTenth(u32),
+    Play,
+    Shuffle,
+    Blacklist,
+    Stop,
+    Resume,
+    Loop,
+    Special,
+    Search,
+    Full,
+    Change,
+    SetNext,
+    Desel,
+    SetAlbum,
+    MouseSupport,
+    Crossfade,
+    Gain,
+    Analyze,
+    QueueAdd,
+    QueueAddAll,
+    QueueRemove,
+    QueueUp,
+    QueueDown,
+    QueueClear,
+    QueueView,
+    HistoryView,
+    Next,
+    Previous,
+    PlaylistsView,
+    LyricsView,
+    PlaylistAdd,
+    PlaylistNew,
+    PlaylistRename,
+    PlaylistRemove,
+    PlaylistImport,
+    PlaylistExport,
+    Library,
+
+    // not bindable, raised by the audio thread, D-Bus, mouse or curses itself
+    TrackEnded,
+    Advanced,
+    Tick,
+    SeekPending,
+    RepeatChanged,
+    Resize,
+}
+
+/// Bindable actions by the name used in keys.toml.
+const NAMES: &[(&str, KeyAction)] = &[
+    ("quit", KeyAction::Quit),
+    ("up", KeyAction::Up),
+    ("down", KeyAction::Down),
+    ("page_up", KeyAction::PageUp),
+    ("page_down", KeyAction::PageDown),
+    ("seek_forward", KeyAction::SeekForward),
+    ("seek_backward", KeyAction::SeekBackward),
+    ("seek_0", KeyAction::SeekTenth(0)),
+    ("seek_10", KeyAction::SeekTenth(1)),
+    ("seek_20", KeyAction::SeekTenth(2)),
+    ("seek_30", KeyAction::SeekTenth(3)),
+    ("seek_40", KeyAction::SeekTenth(4)),
+    ("seek_50", KeyAction::SeekTenth(5)),
+    ("seek_60", KeyAction::SeekTenth(6)),
+    ("seek_70", KeyAction::SeekTenth(7)),
+    ("seek_80", KeyAction::SeekTenth(8)),
+    ("seek_90", KeyAction::SeekTenth(9)),
+    ("play", KeyAction::Play),
+    ("shuffle", KeyAction::Shuffle),
+    ("blacklist", KeyAction::Blacklist),
+    ("stop", KeyAction::Stop),
+    ("resume", KeyAction::Resume),
+    ("loop", KeyAction::Loop),
+    ("special", KeyAction::Special),
+    ("search", KeyAction::Search),
+    ("redraw", KeyAction::Full),
+    ("change_artist", KeyAction::Change),
+    ("set_next", KeyAction::SetNext),
+    ("deselect", KeyAction::Desel),
+    ("set_album", KeyAction::SetAlbum),
+    ("mouse", KeyAction::MouseSupport),
+    ("crossfade", KeyAction::Crossfade),
+    ("gain", KeyAction::Gain),
+    ("analyze", KeyAction::Analyze),
+    ("queue_add", KeyAction::QueueAdd),
+    ("queue_add_all", KeyAction::QueueAddAll),
+    ("queue_remove", KeyAction::QueueRemove),
+    ("queue_up", KeyAction::QueueUp),
+    ("queue_down", KeyAction::QueueDown),
+    ("queue_clear", KeyAction::QueueClear),
+    ("queue", KeyAction::QueueView),
+    ("history", KeyAction::HistoryView),
+    ("next", KeyAction::Next),
+    ("previous", KeyAction::Previous),
+    ("playlists", KeyAction::PlaylistsView),
+    ("lyrics", KeyAction::LyricsView),
+    ("playlist_add", KeyAction::PlaylistAdd),
+    // what playlist_add was called when it set the album tag
+    ("set_playlist", KeyAction::PlaylistAdd),
+    ("playlist_new", KeyAction::PlaylistNew),
+    ("playlist_rename", KeyAction::PlaylistRename),
+    ("playlist_remove", KeyAction::PlaylistRemove),
+    ("playlist_import", KeyAction::PlaylistImport),
+    ("playlist_export", KeyAction::PlaylistExport),
+    ("library", KeyAction::Library),
+];
+
+const DEFAULTS: &[(KeyAction, &[&str])] = &[
+    (KeyAction::Quit, &["q"]),
+    (KeyAction::Up, &["u", "Up"]),
+    (KeyAction::Down, &["j", "Down"]),
+    (KeyAction::PageUp, &["PageUp"]),
+    (KeyAction::PageDown, &["PageDown"]),
+    (KeyAction::SeekForward, &["m", "Right"]),
+    (KeyAction::SeekBackward, &["n", "Left"]),
+    (KeyAction::SeekTenth(0), &["0"]),
+    (KeyAction::SeekTenth(1), &["1"]),
+    (KeyAction::SeekTenth(2), &["2"]),
+    (KeyAction::SeekTenth(3), &["3"]),
+    (KeyAction::SeekTenth(4), &["4"]),
+    (KeyAction::SeekTenth(5), &["5"]),
+    (KeyAction::SeekTenth(6), &["6"]),
+    (KeyAction::SeekTenth(7), &["7"]),
+    (KeyAction::SeekTenth(8), &["8"]),
+    (KeyAction::SeekTenth(9), &["9"]),
+    (KeyAction::Play, &["p"]),
+    (KeyAction::Shuffle, &["f"]),
+    (KeyAction::Blacklist, &["b"]),
+    (KeyAction::Stop, &["s"]),
+    (KeyAction::Resume, &["r"]),
+    (KeyAction::Loop, &["l"]),
+    (KeyAction::Special, &["o"]),
+    (KeyAction::Search, &["h"]),
+    (KeyAction::Full, &["g"]),
+    (KeyAction::Change, &["c"]),
+    (KeyAction::SetNext, &["e"]),
+    (KeyAction::Desel, &["d"]),
+    (KeyAction::MouseSupport, &["t"]),
+    (KeyAction::Crossfade, &["x"]),
+    (KeyAction::Gain, &["z"]),
+    (KeyAction::Analyze, &["a"]),
+    (KeyAction::QueueAdd, &["w"]),
+    (KeyAction::QueueAddAll, &["A-w"]),
+    (KeyAction::QueueRemove, &["-"]),
+    (KeyAction::QueueUp, &["["]),
+    (KeyAction::QueueDown, &["]"]),
+    (KeyAction::QueueClear, &["A-c"]),
+    (KeyAction::QueueView, &["Tab"]),
+    (KeyAction::HistoryView, &["A-h"]),
+    (KeyAction::Next, &["."]),
+    (KeyAction::Previous, &[","]),
+    (KeyAction::PlaylistsView, &["A-p"]),
+    (KeyAction::LyricsView, &["i"]),
+    (KeyAction::PlaylistAdd, &["v"]),
+    (KeyAction::PlaylistNew, &["A-n"]),
+    (KeyAction::PlaylistRename, &["A-r"]),
+    (KeyAction::PlaylistRemove, &["A-d"]),
+    (KeyAction::PlaylistImport, &["A-i"]),
+    (KeyAction::PlaylistExport, &["A-e"]),
+    (KeyAction::Library, &["A-l"]),
+];
+
+/// Alt isn't a curses key, it shows up as Esc followed by the key.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum KeyName {
+    Plain(Input),
+    Alt(char),
+}
+
+pub fn action_name(action: KeyAction) -> &'static str {
+    NAMES
+        .iter()
+        .find(|(_, a)| *a == action)
+        .map(|(n, _)| *n)
+        .unwrap_or("internal")
+}
+
+/// "C-q" -> Character('\x11'), "F5" -> KeyF5, "A-x" -> Alt('x')
+pub fn parse_key(name: &str) -> Option<KeyName> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyName::Plain(Input::Character(c)));
+    }
+    let modified = |prefixes: &[&str]| {
+        prefixes.iter().find_map(|p| {
+            let rest = name.strip_prefix(p)?;
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        })
+    };
+    if let Some(c) = modified(&["C-", "Ctrl-", "Ctrl+"]) {
+        return c.is_ascii_alphabetic().then(|| {
+            KeyName::Plain(Input::Character(
+                (c.to_ascii_lowercase() as u8 & 0x1f) as char,
+            ))
+        });
+    }
+    if let Some(c) = modified(&["A-", "M-", "Alt-", "Alt+"]) {
+        return Some(KeyName::Alt(c));
+    }
+    let input = match name.to_ascii_lowercase().as_str() {
+        "space" => Input::Character(' '),
+        "enter" => Input::Character('\n'),
+        "tab" => Input::Character('\t'),
+        "backspace" => Input::KeyBackspace,
+        "esc" | "escape" => Input::Character('\x1b'),
+        "up" => Input::KeyUp,
+        "down" => Input::KeyDown,
+        "left" => Input::KeyLeft,
+        "right" => Input::KeyRight,
+        "pageup" | "pgup" => Input::KeyPPage,
+        "pagedown" | "pgdn" => Input::KeyNPage,
+        "home" => Input::KeyHome,
+        "end" => Input::KeyEnd,
+        "insert" => Input::KeyIC,
+        "delete" => Input::KeyDC,
+        // F0 and F13 and up carry audio thread reports, see get_input_or_report
+        "f1" => Input::KeyF1,
+        "f2" => Input::KeyF2,
+        "f3" => Input::KeyF3,
+        "f4" => Input::KeyF4,
+        "f5" => Input::KeyF5,
+        "f6" => Input::KeyF6,
+        "f7" => Input::KeyF7,
+        "f8" => Input::KeyF8,
+        "f9" => Input::KeyF9,
+        "f10" => Input::KeyF10,
+        "f11" => Input::KeyF11,
+        "f12" => Input::KeyF12,
+        _ => return None,
+    };
+    Some(KeyName::Plain(input))
+}
+
+pub struct Keymap {
+    binds: HashMap<KeyName, KeyAction>,
+}
+
+impl Keymap {
+    pub fn defaults() -> Self {
+        let mut binds = HashMap::new();
+        for (action, keys) in DEFAULTS {
+            for key in *keys {
+                binds.insert(parse_key(key).unwrap(), *action);
+            }
+        }
+        Self { binds }
+    }
+
+    /// Defaults overridden by ~/.config/neocrystal/keys.toml.
+    /// The returned lines describe everything in the file that was ignored.
+    pub fn load() -> (Self, Vec<String>) {
+        let file = home_dir().map(|h| h.join(".config").join("neocrystal").join("keys.toml"));
+        match file.and_then(|f| fs::read_to_string(f).ok()) {
+            Some(buf) => Self::from_config(&buf),
+            None => (Self::defaults(), Vec::new()),
+        }
+    }
+
+    pub fn from_config(buf: &str) -> (Self, Vec<String>) {
+        let (entries, mut problems) = parse_toml(buf, "keys.toml");
+        let mut user: Vec<(KeyAction, Vec<(KeyName, usize)>)> = Vec::new();
+        // a file without any section header is read as [keys]
+        for entry in entries
+            .into_iter()
+            .filter(|e| e.section.is_empty() || e.section == "keys")
+        {
+            let (n, name) = (entry.line, entry.key.as_str());
+            let Some(action) = NAMES.iter().find(|(a, _)| *a == name).map(|(_, a)| *a) else {
+                problems.push(format!("keys.toml:{}: unknown action `{}`", n, name));
+                continue;
+            };
+            let keys = match entry.value {
+                Value::Str(key) => vec![key],
+                Value::List(keys) => keys,
+                _ => {
+                    problems.push(format!(
+                        "keys.toml:{}: `{}` should be a key name or a list of them",
+                        n, name
+                    ));
+                    continue;
+                }
+            };
+            let mut parsed = Vec::new();
+            for key in keys {
+                match parse_key(&key) {
+                    Some(k) => parsed.push((k, n)),
+                    None => problems.push(format!("keys.toml:{}: unknown key `{}`", n, key)),
+                }
+            }
+            if parsed.is_empty() {
+                // nothing usable, the defaults stay
+                continue;
+            }
+            match user.iter_mut().find(|(a, _)| *a == action) {
+                Some((_, existing)) => existing.extend(parsed),
+                None => user.push((action, parsed)),
+            }
+        }
+
+        let mut keymap = Self::defaults();
+        keymap
+            .binds
+            .retain(|_, action| !user.iter().any(|(a, _)| a == action));
+        let mut bound_at: HashMap<KeyName, usize> = HashMap::new();
+        for (action, keys) in user {
+            for (key, line) in keys {
+                match keymap.binds.get(&key) {
+                    Some(other) if *other != action => {
+                        let origin = match bound_at.get(&key) {
+                            Some(l) => format!("line {}", l),
+                            None => "the defaults".to_string(),
+                        };
+                        problems.push(format!(
+                            "keys.toml:{}: `{}` is already bound to `{}` by {}, ignored",
+                            line,
+                            action_name(action),
+                            action_name(*other),
+                            origin
+                        ));
+                    }
+                    _ => {
+                        keymap.binds.insert(key, action);
+                        bound_at.insert(key, line);
+                    }
+                }
+            }
+        }
+        (keymap, problems)
+    }
+
+    pub fn get(&self, key: KeyName) -> Option<KeyAction> {
+        self.binds.get(&key).copied()
+    }
+
+    /// What the main loop dispatches on. Synthetic keys are fixed, everything else goes through the binds.
+    pub fn resolve(&self, key: KeyName) -> Option<KeyAction> {
+        match key {
+            KeyName::Plain(Input::KeyF13) => Some(KeyAction::TrackEnded),
+            KeyName::Plain(Input::KeyF14) => Some(KeyAction::Tick),
+            KeyName::Plain(Input::KeyF0) => Some(KeyAction::Advanced),
+            KeyName::Plain(Input::KeyMove) => Some(KeyAction::SeekPending),
+            KeyName::Plain(Input::KeyNext) => Some(KeyAction::Next),
+            KeyName::Plain(Input::KeyPrevious) => Some(KeyAction::Previous),
+            KeyName::Plain(Input::KeyResize) => Some(KeyAction::Resize),
+            _ => self.get(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_names() {
+        assert_eq!(parse_key("p"), Some(KeyName::Plain(Input::Character('p'))));
+        assert_eq!(
+            parse_key("C-q"),
+            Some(KeyName::Plain(Input::Character('\x11')))
+        );
+        assert_eq!(parse_key("Alt-x"), Some(KeyName::Alt('x')));
+        assert_eq!(parse_key("pageup"), Some(KeyName::Plain(Input::KeyPPage)));
+        assert_eq!(parse_key("F12"), Some(KeyName::Plain(Input::KeyF12)));
+        assert_eq!(parse_key("F13"), None);
+        assert_eq!(parse_key("C-1"), None);
+    }
+
+    #[test]
+    fn overrides_replace_defaults() {
+        let (map, problems) = Keymap::from_config("[keys]\nplay = [\"k\", \"Enter\"]\n");
+        assert!(problems.is_empty());
+        assert_eq!(map.get(parse_key("k").unwrap()), Some(KeyAction::Play));
+        assert_eq!(map.get(parse_key("Enter").unwrap()), Some(KeyAction::Play));
+        assert_eq!(map.get(parse_key("p").unwrap()), None);
+        assert_eq!(map.get(parse_key("q").unwrap()), Some(KeyAction::Quit));
+    }
+
+    #[test]
+    fn reports_problems() {
+        let (map, problems) = Keymap::from_config(
+            "bogus = \"k\"\nplay = \"Hyper-k\"\nstop = \"q\"\nloop = \"y\"\nshuffle = \"y\"\n",
+        );
+        assert_eq!(problems.len(), 4);
+        assert!(problems[0].contains("unknown action"));
+        assert!(problems[1].contains("unknown key"));
+        assert!(problems[2].contains("the defaults"));
+        assert!(problems[3].contains("line 4"));
+        assert_eq!(map.get(parse_key("q").unwrap()), Some(KeyAction::Quit));
+        assert_eq!(map.get(parse_key("y").unwrap()), Some(KeyAction::Loop));
+    }
+}
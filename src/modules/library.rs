@@ -0,0 +1,757 @@
+// Library scanning. Walks every root recursively and collects files with one of the
+// configured extensions. Hidden directories and files are skipped, symlinks are followed
+// only when the config says so, and every directory is entered once (by its canonical
+// path) so symlink loops and overlapping roots don't repeat songs.
+// Probing files is slow, so results are cached in ~/.cache/neocrystal/library.tsv
+// and only new or changed files get opened.
+
+use crate::modules::audio::audio_duration;
+use crate::modules::config::Config;
+use crate::modules::loudness::{LoudnessCache, ReplayGain, flac_replaygain, id3_replaygain};
+use audiotags::{FlacTag, Id3v2Tag, Tag};
+use home::home_dir;
+use notify::event::{AccessKind, AccessMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// A found file and what its directories say about it, for when tags don't.
+pub struct LibraryFile {
+    pub path: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ScanReport {
+    pub found: usize,
+    pub skipped: usize,
+    /// "path: error" for every file or directory that couldn't be read
+    pub failed: Vec<String>,
+}
+
+impl ScanReport {
+    pub fn summary(&self) -> String {
+        let mut out = format!("{} songs", self.found);
+        if self.skipped > 0 {
+            out += &format!(", {} files skipped", self.skipped);
+        }
+        if !self.failed.is_empty() {
+            out += &format!(", {} unreadable", self.failed.len());
+        }
+        out
+    }
+}
+
+fn is_hidden(name: &OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// "CD1", "Disc 2", "disk03"
+fn is_disc_dir(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    ["cd", "disc", "disk"].iter().any(|prefix| {
+        lower.strip_prefix(prefix).is_some_and(|rest| {
+            let rest = rest.trim_start_matches([' ', '_', '-']);
+            !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit())
+        })
+    })
+}
+
+/// Artist and album from the directories between the root and the file:
+/// Artist/Album/track, Artist/Album/CD1/track, Artist/track.
+pub fn dir_hints(relative: &Path) -> (Option<String>, Option<String>) {
+    let mut dirs: Vec<String> = relative
+        .parent()
+        .map(|p| {
+            p.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    if dirs.last().is_some_and(|d| is_disc_dir(d)) {
+        dirs.pop();
+    }
+    match dirs.as_slice() {
+        [] => (None, None),
+        [artist] => (Some(artist.clone()), None),
+        [.., artist, album] => (Some(artist.clone()), Some(album.clone())),
+    }
+}
+
+/// A file the library should hold: one of the configured extensions and not excluded.
+fn is_wanted(config: &Config, path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|e| config.extensions.contains(&e))
+        && !config.is_excluded(path)
+}
+
+/// Walks start, which is root or a directory under it. Directories already in visited
+/// are not entered again.
+fn walk(
+    config: &Config,
+    root: &Path,
+    start: PathBuf,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<LibraryFile>,
+    report: &mut ScanReport,
+) {
+    let mut stack = vec![start];
+    while let Some(dir) = stack.pop() {
+        let mut entries: Vec<fs::DirEntry> = match fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(Result::ok).collect(),
+            Err(e) => {
+                report.failed.push(format!("{}: {}", dir.display(), e));
+                continue;
+            }
+        };
+        entries.sort_by_key(|e| e.file_name());
+        let mut subdirs = Vec::new();
+        for entry in entries {
+            let path = entry.path();
+            let is_link = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if is_hidden(&entry.file_name()) || (is_link && !config.follow_symlinks) {
+                if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    report.skipped += 1;
+                }
+                continue;
+            }
+            // follows the link, if there is one
+            let meta = match fs::metadata(&path) {
+                Ok(meta) => meta,
+                Err(e) => {
+                    report.failed.push(format!("{}: {}", path.display(), e));
+                    continue;
+                }
+            };
+            if meta.is_dir() {
+                if config.is_excluded(&path) {
+                    continue;
+                }
+                match fs::canonicalize(&path) {
+                    // already scanned means a loop or an overlapping root
+                    Ok(canonical) => {
+                        if visited.insert(canonical) {
+                            subdirs.push(path);
+                        }
+                    }
+                    Err(e) => report.failed.push(format!("{}: {}", path.display(), e)),
+                }
+                continue;
+            }
+            if !is_wanted(config, &path) {
+                report.skipped += 1;
+                continue;
+            }
+            let (artist, album) = dir_hints(path.strip_prefix(root).unwrap_or(&path));
+            files.push(LibraryFile {
+                path: path.display().to_string(),
+                artist,
+                album,
+            });
+        }
+        // popped in name order
+        stack.extend(subdirs.into_iter().rev());
+    }
+}
+
+pub fn scan(config: &Config) -> (Vec<LibraryFile>, ScanReport) {
+    let mut files = Vec::new();
+    let mut report = ScanReport::default();
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+
+    for root in &config.roots {
+        let root = PathBuf::from(root);
+        match fs::canonicalize(&root) {
+            Ok(canonical) => {
+                if !visited.insert(canonical) {
+                    continue;
+                }
+            }
+            Err(e) => {
+                report.failed.push(format!("{}: {}", root.display(), e));
+                continue;
+            }
+        }
+        walk(
+            config,
+            &root,
+            root.clone(),
+            &mut visited,
+            &mut files,
+            &mut report,
+        );
+    }
+    report.found = files.len();
+    (files, report)
+}
+
+/// The common tag fields, None when the file doesn't have them.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track: Option<u16>,
+    pub disc: Option<u16>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// Everything read from the file itself: duration and tag fields.
+#[derive(Clone, Default)]
+pub struct Probed {
+    pub duration: Duration,
+    pub tags: Tags,
+    pub gain: ReplayGain,
+}
+
+/// Blank tags are as good as missing ones.
+fn text(field: Option<&str>) -> Option<String> {
+    field
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(String::from)
+}
+
+/// Tag fields and ReplayGain, both from the one read of the tags. ReplayGain is only in
+/// ID3 and FLAC tags.
+fn read_tags(path: &str) -> (Tags, ReplayGain) {
+    let Ok(tag) = Tag::new().read_from_path(path) else {
+        return (Tags::default(), ReplayGain::default());
+    };
+    let tags = Tags {
+        title: text(tag.title()),
+        artist: tag
+            .artists()
+            .map(|a| a.join(", "))
+            .filter(|a| !a.is_empty()),
+        album: text(tag.album_title()),
+        album_artist: tag
+            .album_artists()
+            .map(|a| a.join(", "))
+            .filter(|a| !a.is_empty()),
+        track: tag.track_number(),
+        disc: tag.disc_number(),
+        year: tag.year(),
+        genre: text(tag.genre()),
+        composer: text(tag.composer()),
+        comment: text(tag.comment()),
+    };
+    let gain = if tag.to_any().is::<Id3v2Tag>() {
+        id3_replaygain(&tag.into())
+    } else if tag.to_any().is::<FlacTag>() {
+        flac_replaygain(&tag.into())
+    } else {
+        ReplayGain::default()
+    };
+    (tags, gain)
+}
+
+/// Reads the file once for tags and ReplayGain and once for the duration.
+pub fn probe(path: &str) -> Probed {
+    let (tags, gain) = read_tags(path);
+    Probed {
+        duration: audio_duration(path),
+        tags,
+        gain,
+    }
+}
+
+/// (mtime, size), what decides whether a cached probe is still good.
+fn stamp(path: &str) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime = meta
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some((mtime, meta.len()))
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('t')) => {
+                out.push('\t');
+                chars.next();
+            }
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                out.push('\\');
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn opt_field(field: &str) -> Option<String> {
+    (!field.is_empty()).then(|| unescape(field))
+}
+
+const CACHE_HEADER: &str = "neocrystal-library 2";
+
+/// (mtime, size) and what was read from the file
+type CacheEntry = ((u64, u64), Probed);
+
+/// Probes of every file in the library keyed by path, valid while mtime and size match.
+/// One line per file: path, mtime, size, duration in ms, the ten tag fields in the order
+/// of Tags and the four ReplayGain fields, tab separated. Missing values are empty fields.
+/// Caches with another header are thrown away and rebuilt.
+pub struct LibraryCache {
+    file: Option<PathBuf>,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl LibraryCache {
+    pub fn load() -> Self {
+        let file = home_dir().map(|h| h.join(".cache").join("neocrystal").join("library.tsv"));
+        let entries = file
+            .as_ref()
+            .and_then(|f| fs::read_to_string(f).ok())
+            .filter(|buf| buf.lines().next() == Some(CACHE_HEADER))
+            .map(|buf| buf.lines().skip(1).filter_map(parse_cache_line).collect())
+            .unwrap_or_default();
+        Self { file, entries }
+    }
+
+    pub fn get(&self, path: &str, stamp: (u64, u64)) -> Option<Probed> {
+        self.entries
+            .get(path)
+            .filter(|(s, _)| *s == stamp)
+            .map(|(_, p)| p.clone())
+    }
+
+    pub fn insert(&mut self, path: &str, stamp: (u64, u64), probed: Probed) {
+        self.entries.insert(path.to_string(), (stamp, probed));
+    }
+
+    /// Drops files that are gone from the library.
+    pub fn retain(&mut self, keep: &HashSet<String>) {
+        self.entries.retain(|path, _| keep.contains(path));
+    }
+
+    pub fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if let Some(parent) = file.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let mut buf = String::from(CACHE_HEADER);
+        buf.push('\n');
+        for (path, entry) in &self.entries {
+            buf += &format_cache_line(path, entry);
+        }
+        let _ = fs::write(file, buf);
+    }
+}
+
+fn format_cache_line(path: &str, ((mtime, size), p): &CacheEntry) -> String {
+    let gain = |g: Option<f32>| g.map(|g| g.to_string()).unwrap_or_default();
+    let text = |t: &Option<String>| escape(t.as_deref().unwrap_or(""));
+    let number = |n: Option<i32>| n.map(|n| n.to_string()).unwrap_or_default();
+    let t = &p.tags;
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+        escape(path),
+        mtime,
+        size,
+        p.duration.as_millis(),
+        text(&t.title),
+        text(&t.artist),
+        text(&t.album),
+        text(&t.album_artist),
+        number(t.track.map(i32::from)),
+        number(t.disc.map(i32::from)),
+        number(t.year),
+        text(&t.genre),
+        text(&t.composer),
+        text(&t.comment),
+        gain(p.gain.track_gain),
+        gain(p.gain.track_peak),
+        gain(p.gain.album_gain),
+        gain(p.gain.album_peak),
+    )
+}
+
+fn parse_cache_line(line: &str) -> Option<(String, CacheEntry)> {
+    let f: Vec<&str> = line.split('\t').collect();
+    if f.len() != 18 {
+        return None;
+    }
+    let gain = |s: &str| s.parse().ok();
+    let probed = Probed {
+        duration: Duration::from_millis(f[3].parse().ok()?),
+        tags: Tags {
+            title: opt_field(f[4]),
+            artist: opt_field(f[5]),
+            album: opt_field(f[6]),
+            album_artist: opt_field(f[7]),
+            track: f[8].parse().ok(),
+            disc: f[9].parse().ok(),
+            year: f[10].parse().ok(),
+            genre: opt_field(f[11]),
+            composer: opt_field(f[12]),
+            comment: opt_field(f[13]),
+        },
+        gain: ReplayGain {
+            track_gain: gain(f[14]),
+            track_peak: gain(f[15]),
+            album_gain: gain(f[16]),
+            album_peak: gain(f[17]),
+            analyzed: None,
+        },
+    };
+    Some((
+        unescape(f[0]),
+        ((f[1].parse().ok()?, f[2].parse().ok()?), probed),
+    ))
+}
+
+pub enum LibraryEvent {
+    /// New songs, or new probes of songs already in the list
+    Found(Vec<(LibraryFile, Probed)>),
+    /// Files or whole directories that are gone
+    Removed(Vec<String>),
+    Done(ScanReport),
+}
+
+/// Probed results are handed over in batches of this size, or whatever is there
+/// after BATCH_WAIT, so the list doesn't get re-sorted for every single file.
+const BATCH_SIZE: usize = 256;
+const BATCH_WAIT: Duration = Duration::from_millis(250);
+
+/// Scans, answers from the cache and probes the rest on a few worker threads, all in
+/// the background. Cached files come in the first batch, Done comes last.
+pub fn spawn_load(config: Config, tx: Sender<LibraryEvent>) {
+    thread::spawn(move || {
+        let (files, report) = scan(&config);
+        let mut cache = LibraryCache::load();
+        let loudness = LoudnessCache::load();
+        cache.retain(&files.iter().map(|f| f.path.clone()).collect());
+
+        let mut ready = Vec::new();
+        let mut pending = Vec::new();
+        for file in files {
+            let stamp = stamp(&file.path);
+            match stamp.and_then(|s| cache.get(&file.path, s)) {
+                Some(probed) => ready.push((file, probed)),
+                None => pending.push((file, stamp)),
+            }
+        }
+        let fill = |batch: &mut Vec<(LibraryFile, Probed)>| {
+            for (file, probed) in batch.iter_mut() {
+                if !probed.gain.is_tagged() {
+                    probed.gain.analyzed = loudness.get(&file.path);
+                }
+            }
+        };
+        fill(&mut ready);
+        if tx.send(LibraryEvent::Found(ready)).is_err() {
+            return;
+        }
+
+        let queue = Arc::new(Mutex::new(pending.into_iter()));
+        let (result_tx, result_rx) = mpsc::channel();
+        let workers = thread::available_parallelism()
+            .map_or(2, |n| n.get())
+            .min(8);
+        for _ in 0..workers {
+            let queue = queue.clone();
+            let result_tx = result_tx.clone();
+            thread::spawn(move || {
+                loop {
+                    let next = queue.lock().unwrap().next();
+                    let Some((file, stamp)) = next else {
+                        break;
+                    };
+                    let probed = probe(&file.path);
+                    if result_tx.send((file, stamp, probed)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(result_tx);
+
+        let mut batch = Vec::new();
+        let mut since = Instant::now();
+        loop {
+            let result = result_rx.recv_timeout(BATCH_WAIT);
+            let finished = matches!(result, Err(RecvTimeoutError::Disconnected));
+            if let Ok((file, stamp, probed)) = result {
+                if let Some(stamp) = stamp {
+                    cache.insert(&file.path, stamp, probed.clone());
+                }
+                batch.push((file, probed));
+            }
+            if !batch.is_empty()
+                && (finished || batch.len() >= BATCH_SIZE || since.elapsed() >= BATCH_WAIT)
+            {
+                fill(&mut batch);
+                if tx
+                    .send(LibraryEvent::Found(std::mem::take(&mut batch)))
+                    .is_err()
+                {
+                    break;
+                }
+                since = Instant::now();
+            }
+            if finished {
+                break;
+            }
+        }
+        cache.save();
+        let _ = tx.send(LibraryEvent::Done(report));
+    });
+}
+
+/// Changes are collected until the tree has been quiet this long...
+const SETTLE: Duration = Duration::from_millis(500);
+/// ...or until the oldest one has waited this long, for long copies.
+const SETTLE_MAX: Duration = Duration::from_secs(3);
+
+fn has_hidden_part(relative: &Path) -> bool {
+    relative.components().any(|c| is_hidden(c.as_os_str()))
+}
+
+/// Looks at what changed paths are now: files get probed again, new directories get
+/// walked, missing paths are gone (along with everything under them).
+fn apply_changes(config: &Config, paths: Vec<PathBuf>, tx: &Sender<LibraryEvent>) -> bool {
+    let mut files = Vec::new();
+    let mut removed = Vec::new();
+    let mut report = ScanReport::default();
+    for path in paths {
+        let Some(root) = config
+            .roots
+            .iter()
+            .map(Path::new)
+            .find(|r| path.starts_with(r))
+        else {
+            continue;
+        };
+        if has_hidden_part(path.strip_prefix(root).unwrap_or(&path)) {
+            continue;
+        }
+        match fs::metadata(&path) {
+            Err(_) => removed.push(path.display().to_string()),
+            Ok(meta) if meta.is_dir() => walk(
+                config,
+                root,
+                path,
+                &mut HashSet::new(),
+                &mut files,
+                &mut report,
+            ),
+            Ok(_) if is_wanted(config, &path) => {
+                let (artist, album) = dir_hints(path.strip_prefix(root).unwrap_or(&path));
+                files.push(LibraryFile {
+                    path: path.display().to_string(),
+                    artist,
+                    album,
+                });
+            }
+            Ok(_) => (),
+        }
+    }
+    let mut seen = HashSet::new();
+    files.retain(|f| seen.insert(f.path.clone()));
+
+    if !removed.is_empty() && tx.send(LibraryEvent::Removed(removed)).is_err() {
+        return false;
+    }
+    if files.is_empty() {
+        return true;
+    }
+    let loudness = LoudnessCache::load();
+    let found = files
+        .into_iter()
+        .map(|file| {
+            let mut probed = probe(&file.path);
+            if !probed.gain.is_tagged() {
+                probed.gain.analyzed = loudness.get(&file.path);
+            }
+            (file, probed)
+        })
+        .collect();
+    tx.send(LibraryEvent::Found(found)).is_ok()
+}
+
+/// Watches the library roots and reports changes as Found and Removed events.
+/// The watcher stops when the returned handle is dropped.
+pub fn spawn_watch(config: Config, tx: Sender<LibraryEvent>) -> Option<RecommendedWatcher> {
+    let (raw_tx, raw_rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = raw_tx.send(event);
+    })
+    .ok()?;
+    let watching = config
+        .roots
+        .iter()
+        .filter(|root| {
+            watcher
+                .watch(Path::new(root), RecursiveMode::Recursive)
+                .is_ok()
+        })
+        .count();
+    if watching == 0 {
+        return None;
+    }
+    thread::spawn(move || {
+        let mut dirty: HashSet<PathBuf> = HashSet::new();
+        let (mut oldest, mut latest) = (Instant::now(), Instant::now());
+        loop {
+            match raw_rx.recv_timeout(SETTLE) {
+                Ok(Ok(event)) => {
+                    // playing a file opens and reads it, that's not a change
+                    let read_only = matches!(
+                        event.kind,
+                        EventKind::Access(kind) if kind != AccessKind::Close(AccessMode::Write)
+                    );
+                    if !read_only && !event.paths.is_empty() {
+                        if dirty.is_empty() {
+                            oldest = Instant::now();
+                        }
+                        latest = Instant::now();
+                        dirty.extend(event.paths);
+                    }
+                }
+                Ok(Err(_)) | Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            let settled = latest.elapsed() >= SETTLE || oldest.elapsed() >= SETTLE_MAX;
+            if settled && !dirty.is_empty() && !apply_changes(&config, dirty.drain().collect(), &tx)
+            {
+                break;
+            }
+        }
+    });
+    Some(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_from_directories() {
+        assert_eq!(dir_hints(Path::new("song.mp3")), (None, None));
+        assert_eq!(
+            dir_hints(Path::new("Artist/song.mp3")),
+            (Some("Artist".into()), None)
+        );
+        assert_eq!(
+            dir_hints(Path::new("Genre/Artist/Album/song.mp3")),
+            (Some("Artist".into()), Some("Album".into()))
+        );
+        assert_eq!(
+            dir_hints(Path::new("Artist/Album/Disc 2/song.mp3")),
+            (Some("Artist".into()), Some("Album".into()))
+        );
+    }
+
+    #[test]
+    fn cache_line_round_trip() {
+        let probed = Probed {
+            duration: Duration::from_millis(183_250),
+            tags: Tags {
+                artist: Some("Tab\tand\\slash".into()),
+                track: Some(7),
+                year: Some(1999),
+                ..Tags::default()
+            },
+            gain: ReplayGain {
+                track_gain: Some(-6.5),
+                ..ReplayGain::default()
+            },
+        };
+        let line = format_cache_line("/m/a\nb.flac", &((17, 4096), probed));
+        assert_eq!(line.matches('\n').count(), 1);
+        let (path, ((mtime, size), back)) = parse_cache_line(line.trim_end_matches('\n')).unwrap();
+        assert_eq!(path, "/m/a\nb.flac");
+        assert_eq!((mtime, size), (17, 4096));
+        assert_eq!(back.duration, Duration::from_millis(183_250));
+        assert_eq!(back.tags.artist.as_deref(), Some("Tab\tand\\slash"));
+        assert_eq!(back.tags.album, None);
+        assert_eq!((back.tags.track, back.tags.disc), (Some(7), None));
+        assert_eq!(back.tags.year, Some(1999));
+        assert_eq!(back.gain.track_gain, Some(-6.5));
+        assert_eq!(back.gain.album_gain, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tags_and_gain_from_one_read() {
+        use id3::TagLike;
+        let path = std::env::temp_dir().join(format!("neocrystal-tags-{}.mp3", std::process::id()));
+        let mut tag = id3::Tag::new();
+        tag.set_title("Song");
+        tag.add_frame(id3::frame::ExtendedText {
+            description: "REPLAYGAIN_TRACK_GAIN".into(),
+            value: "-6.50 dB".into(),
+        });
+        fs::write(&path, []).unwrap();
+        tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+        let (tags, gain) = read_tags(&path.display().to_string());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(gain.track_gain, Some(-6.5));
+        assert_eq!(gain.album_gain, None);
+    }
+
+    #[test]
+    fn recursive_scan_survives_loops() {
+        let root = std::env::temp_dir().join(format!("neocrystal-scan-{}", std::process::id()));
+        let album = root.join("Artist").join("Album");
+        fs::create_dir_all(&album).unwrap();
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::write(album.join("a.mp3"), b"").unwrap();
+        fs::write(album.join("cover.jpg"), b"").unwrap();
+        fs::write(root.join(".hidden").join("b.mp3"), b"").unwrap();
+        std::os::unix::fs::symlink(&root, album.join("loop")).unwrap();
+
+        let config = Config {
+            roots: vec![root.display().to_string()],
+            ..Config::default()
+        };
+        let (files, report) = scan(&config);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].artist.as_deref(), Some("Artist"));
+        assert_eq!(files[0].album.as_deref(), Some("Album"));
+        assert_eq!(report.skipped, 1);
+        assert!(report.failed.is_empty());
+
+        let config = Config {
+            follow_symlinks: false,
+            ..config
+        };
+        assert_eq!(scan(&config).1.skipped, 2);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+// ReplayGain / loudness normalization.
+// Gains come from tags first (ID3 TXXX frames, FLAC vorbis comments).
+// Files without tags can get an EBU R128 measurement instead, which is slow
+// so it runs on its own thread and gets cached in ~/.cache/neocrystal/r128.tsv
+
+use ebur128::{EbuR128, Mode};
+use home::home_dir;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::UNIX_EPOCH;
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::DecoderOptions,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+/// ReplayGain 2.0 reference level, analyzed gains are relative to it.
+const REFERENCE_LUFS: f64 = -18.0;
+/// Don't boost quiet tracks more than this, it only brings up the noise floor.
+const MAX_BOOST_DB: f32 = 12.0;
+
+static ANALYZING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GainMode {
+    Off,
+    Track,
+    Album,
+}
+
+impl GainMode {
+    pub fn cycle(self) -> Self {
+        match self {
+            GainMode::Off => GainMode::Track,
+            GainMode::Track => GainMode::Album,
+            GainMode::Album => GainMode::Off,
+        }
+    }
+    pub fn label(self) -> &'static str {
+        match self {
+            GainMode::Off => "off",
+            GainMode::Track => "trk",
+            GainMode::Album => "alb",
+        }
+    }
+}
+
+/// Gains are in dB, peaks are linear (1.0 = full scale).
+#[derive(Copy, Clone, Default)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+    pub analyzed: Option<f32>, // R128 fallback when there are no tags
+}
+
+impl ReplayGain {
+    /// Linear factor to multiply the samples with. Album mode falls back to track gain,
+    /// both fall back to the analyzed gain. Peaks keep the result from clipping.
+    pub fn factor(&self, mode: GainMode) -> f32 {
+        let (gain, peak) = match mode {
+            GainMode::Off => return 1.0,
+            GainMode::Track => (self.track_gain, self.track_peak),
+            GainMode::Album => (
+                self.album_gain.or(self.track_gain),
+                self.album_peak.or(self.track_peak),
+            ),
+        };
+        let Some(db) = gain.or(self.analyzed) else {
+            return 1.0;
+        };
+        let factor = 10f32.powf(db.min(MAX_BOOST_DB) / 20.0);
+        match peak {
+            Some(p) if p > 0.0 => factor.min(1.0 / p),
+            _ => factor,
+        }
+    }
+    pub fn is_tagged(&self) -> bool {
+        self.track_gain.is_some() || self.album_gain.is_some()
+    }
+}
+
+/// "-6.54 dB" -> -6.54
+fn parse_gain_value(s: &str) -> Option<f32> {
+    let s = s.trim();
+    let s = s
+        .strip_suffix("dB")
+        .or_else(|| s.strip_suffix("db"))
+        .unwrap_or(s);
+    s.trim().parse().ok()
+}
+
+fn apply_field(rg: &mut ReplayGain, key: &str, value: &str) {
+    let value = parse_gain_value(value);
+    match key.to_ascii_uppercase().as_str() {
+        "REPLAYGAIN_TRACK_GAIN" => rg.track_gain = value,
+        "REPLAYGAIN_TRACK_PEAK" => rg.track_peak = value,
+        "REPLAYGAIN_ALBUM_GAIN" => rg.album_gain = value,
+        "REPLAYGAIN_ALBUM_PEAK" => rg.album_peak = value,
+        _ => {}
+    }
+}
+
+/// ReplayGain fields from FLAC vorbis comments, all None if there are none.
+pub fn flac_replaygain(tag: &metaflac::Tag) -> ReplayGain {
+    let mut rg = ReplayGain::default();
+    if let Some(comments) = tag.vorbis_comments() {
+        for (key, values) in &comments.comments {
+            if let Some(value) = values.first() {
+                apply_field(&mut rg, key, value);
+            }
+        }
+    }
+    rg
+}
+
+/// ReplayGain fields from ID3 TXXX frames, all None if there are none.
+pub fn id3_replaygain(tag: &id3::Tag) -> ReplayGain {
+    let mut rg = ReplayGain::default();
+    for txxx in tag.extended_texts() {
+        apply_field(&mut rg, &txxx.description, &txxx.value);
+    }
+    rg
+}
+
+/// Measures integrated loudness of the whole file and returns the gain (dB)
+/// that brings it to the reference level.
+pub fn analyze(path: &str) -> Option<f32> {
+    let file = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.rsplit('.').next() {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+    let mut format = probed.format;
+    let track = format.default_track()?;
+    let track_id = track.id;
+    let channels = track.codec_params.channels?.count() as u32;
+    let rate = track.codec_params.sample_rate?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .ok()?;
+    let mut meter = EbuR128::new(channels, rate, Mode::I).ok()?;
+    let mut buf: Option<SampleBuffer<f32>> = None;
+
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let Ok(decoded) = decoder.decode(&packet) else {
+            continue;
+        };
+        if buf.as_ref().is_none_or(|b| b.capacity() < decoded.capacity()) {
+            buf = Some(SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        }
+        let b = buf.as_mut().unwrap();
+        b.copy_interleaved_ref(decoded);
+        meter.add_frames_f32(b.samples()).ok()?;
+    }
+
+    let lufs = meter.loudness_global().ok()?;
+    if !lufs.is_finite() {
+        return None;
+    }
+    Some((REFERENCE_LUFS - lufs) as f32)
+}
+
+fn mtime(path: &str) -> u64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Analyzed gains keyed by path, invalidated when the file's mtime changes.
+/// File format is one "mtime\tgain\tpath" per line.
+pub struct LoudnessCache {
+    file: Option<PathBuf>,
+    entries: HashMap<String, (u64, f32)>,
+}
+
+impl LoudnessCache {
+    pub fn load() -> Self {
+        let file = home_dir().map(|h| h.join(".cache").join("neocrystal").join("r128.tsv"));
+        let mut entries = HashMap::new();
+        if let Some(buf) = file.as_ref().and_then(|f| fs::read_to_string(f).ok()) {
+            for line in buf.lines() {
+                let mut f = line.splitn(3, '\t');
+                let (Some(m), Some(g), Some(p)) = (f.next(), f.next(), f.next()) else {
+                    continue;
+                };
+                if let (Ok(m), Ok(g)) = (m.parse(), g.parse()) {
+                    entries.insert(p.to_string(), (m, g));
+                }
+            }
+        }
+        Self { file, entries }
+    }
+    pub fn get(&self, path: &str) -> Option<f32> {
+        self.entries
+            .get(path)
+            .filter(|(m, _)| *m == mtime(path))
+            .map(|(_, g)| *g)
+    }
+    pub fn insert(&mut self, path: &str, gain: f32) {
+        self.entries.insert(path.to_string(), (mtime(path), gain));
+    }
+    pub fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if let Some(parent) = file.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let buf: String = self
+            .entries
+            .iter()
+            .map(|(p, (m, g))| format!("{}\t{}\t{}\n", m, g, p))
+            .collect();
+        let _ = fs::write(file, buf);
+    }
+}
+
+/// Analyzes paths one by one on a background thread, results go to tx as (path, gain).
+/// Returns false if a pass is already running.
+pub fn spawn_analysis(paths: Vec<String>, tx: Sender<(String, f32)>) -> bool {
+    if ANALYZING.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    thread::spawn(move || {
+        let mut cache = LoudnessCache::load();
+        for (i, path) in paths.iter().enumerate() {
+            let gain = match cache.get(path) {
+                Some(g) => g,
+                None => match analyze(path) {
+                    Some(g) => {
+                        cache.insert(path, g);
+                        g
+                    }
+                    None => continue,
+                },
+            };
+            if tx.send((path.clone(), gain)).is_err() {
+                break;
+            }
+            if i % 25 == 24 {
+                cache.save();
+            }
+        }
+        cache.save();
+        ANALYZING.store(false, Ordering::SeqCst);
+    });
+    true
+}
@@ -0,0 +1,276 @@
+// LRC lyrics turned into the same events ASS subtitles load into.
+//
+//   [ti:Song]            metadata, the title is kept
+//   [offset:+250]        milliseconds, positive shows every line sooner
+//   [00:12.30]First line
+//   [00:15.00][01:15.00]A chorus line that comes twice
+//   [00:18.00]<00:18.00>Word <00:18.40>by <00:18.90>word
+//
+// Word times become \k karaoke tags, the same way an ASS line times its syllables.
+// A line lasts until the next one starts.
+
+use crate::libkagami::complex::overrides::ASSOverride;
+use crate::libkagami::complex::types::AssTime;
+use crate::libkagami::core::{Event, ScriptInfo, SubstationAlpha};
+use crate::libkagami::tags::{ASSLine, ASSText};
+
+/// How long the last line stays when nothing says when it ends.
+const LAST_LINE_MS: i64 = 10_000;
+
+/// Offsets further than a day either way are typos, they're ignored.
+const MAX_OFFSET_MS: i64 = 86_400_000;
+
+/// "mm:ss", "mm:ss.xx", "mm:ss.xxx" or "mm:ss:xx" in milliseconds.
+fn parse_time(text: &str) -> Option<i64> {
+    let (minutes, rest) = text.trim().split_once(':')?;
+    let (seconds, fraction) = match rest.split_once(['.', ':']) {
+        Some((s, f)) => (s, f),
+        None => (rest, ""),
+    };
+    let minutes: i64 = minutes.parse().ok()?;
+    let seconds: i64 = seconds.parse().ok()?;
+    if !(0..60).contains(&seconds) || minutes < 0 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    // .5 is 500 ms, .05 is 50 ms, .005 is 5 ms
+    let ms = match fraction.len() {
+        0 => 0,
+        n => fraction[..n.min(3)].parse::<i64>().ok()? * 10i64.pow(3 - n.min(3) as u32),
+    };
+    minutes
+        .checked_mul(60_000)?
+        .checked_add(seconds * 1000 + ms)
+}
+
+pub fn ass_time(ms: i64) -> AssTime {
+    let cs = ms.max(0) / 10;
+    AssTime {
+        hours: (cs / 360_000).min(u8::MAX as i64) as u8,
+        minutes: (cs / 6000 % 60) as u8,
+        seconds: (cs / 100 % 60) as u8,
+        centiseconds: (cs % 100) as u8,
+    }
+}
+
+/// An event the way the ASS loader makes them, without styles.
+pub fn event(start_ms: i64, end_ms: i64, layer: u16, data: Vec<ASSText>) -> Event {
+    Event {
+        layer,
+        start: ass_time(start_ms),
+        end: ass_time(end_ms),
+        style: "Default".to_string(),
+        name: String::new(),
+        margin_l: 0,
+        margin_r: 0,
+        margin_v: 0,
+        effect: String::new(),
+        text: ASSLine {
+            current_overrides: vec![],
+            data,
+        },
+    }
+}
+
+pub fn empty_script(title: String) -> ScriptInfo {
+    ScriptInfo {
+        title,
+        script_type: String::new(),
+        wrap_style: 0,
+        scaled_border_and_shadow: false,
+        ycbcr_matrix: String::new(),
+        playresx: 0,
+        playresy: 0,
+    }
+}
+
+/// Splits "[a][b]rest" into the bracket contents and rest.
+fn leading_tags(line: &str) -> (Vec<&str>, &str) {
+    let mut tags = Vec::new();
+    let mut rest = line.trim_start();
+    while let Some(inner) = rest.strip_prefix('[')
+        && let Some((tag, after)) = inner.split_once(']')
+    {
+        tags.push(tag);
+        rest = after;
+    }
+    (tags, rest)
+}
+
+/// The words of a line and when each starts.
+type Words = Vec<(Option<i64>, String)>;
+
+/// The text of a line cut at its <mm:ss.xx> word times. The text before the first
+/// time has none.
+fn words(text: &str) -> Words {
+    let mut parts = vec![(None, String::new())];
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        let time = rest[open + 1..]
+            .split_once('>')
+            .and_then(|(t, after)| Some((parse_time(t)?, after)));
+        let last = &mut parts.last_mut().unwrap().1;
+        match time {
+            Some((ms, after)) => {
+                *last += &rest[..open];
+                parts.push((Some(ms), String::new()));
+                rest = after;
+            }
+            None => {
+                *last += &rest[..open + 1];
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    parts.last_mut().unwrap().1 += rest;
+    parts
+}
+
+/// A time with nothing after it says when the word before it ends.
+fn words_end(parts: &[(Option<i64>, String)]) -> Option<i64> {
+    parts
+        .last()
+        .filter(|(_, word)| word.trim().is_empty())
+        .and_then(|(ms, _)| *ms)
+}
+
+/// The line as event text, every timed word a \k syllable lasting until the next one
+/// starts. A pause before a word is an empty syllable.
+fn karaoke(parts: &[(Option<i64>, String)], start: i64, end: i64) -> Vec<ASSText> {
+    if parts.len() == 1 {
+        return vec![ASSText::RawText(parts[0].1.trim().to_string())];
+    }
+    let mut data = Vec::new();
+    let mut sung = start;
+    for (n, (time, word)) in parts.iter().enumerate() {
+        let time = time.unwrap_or(start);
+        let next = parts.get(n + 1).and_then(|(t, _)| *t).unwrap_or(end);
+        if time > sung {
+            data.push(ASSText::Override(ASSOverride::K(
+                ((time - sung) / 10) as u32,
+            )));
+            data.push(ASSText::RawText(String::new()));
+            sung = time;
+        }
+        if word.is_empty() {
+            continue;
+        }
+        let length = (next - sung).max(0);
+        data.push(ASSText::Override(ASSOverride::K((length / 10) as u32)));
+        data.push(ASSText::RawText(word.clone()));
+        sung += length;
+    }
+    data
+}
+
+/// Every timed line of an LRC file, in time order. Lines without a time and
+/// unknown headers are skipped.
+pub fn parse_lrc(buf: &str) -> SubstationAlpha {
+    let mut title = String::new();
+    let mut offset = 0i64;
+    let mut lines: Vec<(i64, Words)> = Vec::new();
+    for line in buf.trim_start_matches('\u{feff}').lines() {
+        let (tags, text) = leading_tags(line);
+        let mut times = Vec::new();
+        for tag in tags {
+            match tag.split_once(':') {
+                Some((key, value)) if key.eq_ignore_ascii_case("ti") => {
+                    title = value.trim().to_string()
+                }
+                Some((key, value)) if key.eq_ignore_ascii_case("offset") => {
+                    offset = (value.trim().trim_start_matches('+').parse().ok())
+                        .filter(|ms: &i64| (-MAX_OFFSET_MS..=MAX_OFFSET_MS).contains(ms))
+                        .unwrap_or(0)
+                }
+                _ => times.extend(parse_time(tag)),
+            }
+        }
+        for start in times {
+            lines.push((start, words(text)));
+        }
+    }
+    lines.sort_by_key(|(start, _)| *start);
+
+    let mut events = Vec::new();
+    for (n, (start, parts)) in lines.iter().enumerate() {
+        // lines that start together all show until the next time
+        let next = lines[n..].iter().map(|l| l.0).find(|s| s > start);
+        let end = match (words_end(parts), next) {
+            (Some(end), Some(next)) => end.min(next),
+            (Some(end), None) | (None, Some(end)) => end,
+            (None, None) => start.saturating_add(LAST_LINE_MS),
+        };
+        let data = karaoke(parts, *start, end);
+        let (start, end) = (start.saturating_sub(offset), end.saturating_sub(offset));
+        events.push(event(start, end, 0, data));
+    }
+    SubstationAlpha {
+        script_info: empty_script(title),
+        v4p_styles: vec![],
+        events,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(data: &[ASSText]) -> String {
+        data.iter()
+            .map(|t| match t {
+                ASSText::RawText(s) => s.clone(),
+                ASSText::Override(ASSOverride::K(cs)) => format!("{{{}}}", cs),
+                ASSText::Override(_) => String::new(),
+            })
+            .collect()
+    }
+
+    fn secs(t: &AssTime) -> f64 {
+        t.minutes as f64 * 60.0 + t.seconds as f64 + t.centiseconds as f64 / 100.0
+    }
+
+    #[test]
+    fn times_and_headers() {
+        assert_eq!(parse_time("01:02.5"), Some(62_500));
+        assert_eq!(parse_time("01:02.05"), Some(62_050));
+        assert_eq!(parse_time("01:02:345"), Some(62_345));
+        assert_eq!(parse_time("1:02"), Some(62_000));
+        assert_eq!(parse_time("ar:Band"), None);
+        assert_eq!(parse_time("00:61.00"), None);
+        assert_eq!(parse_time("999999999999999999:00.00"), None);
+
+        let lrc = "\u{feff}[ti:Song]\r\n[ar:Band]\n[offset:+500]\n[00:12.00][00:20.00]Chorus\n\
+                   [00:15.00]Verse\nno time here\n[00:25.00]\n";
+        let subs = parse_lrc(lrc);
+        assert_eq!(subs.script_info.title, "Song");
+        let events: Vec<(f64, f64, String)> = subs
+            .events
+            .iter()
+            .map(|e| (secs(&e.start), secs(&e.end), text(&e.text.data)))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (11.5, 14.5, "Chorus".to_string()),
+                (14.5, 19.5, "Verse".to_string()),
+                (19.5, 24.5, "Chorus".to_string()),
+                (24.5, 34.5, String::new()),
+            ]
+        );
+
+        // an offset that can't be right is ignored rather than overflowing
+        let subs = parse_lrc("[offset:-9223372036854775808]\n[00:01.00]Line\n");
+        assert_eq!(secs(&subs.events[0].start), 1.0);
+    }
+
+    #[test]
+    fn word_times_become_karaoke() {
+        let subs = parse_lrc("[00:10.00]<00:10.00>One <00:10.50>two <00:11.50>three<00:12.00>\n");
+        let event = &subs.events[0];
+        assert_eq!(text(&event.text.data), "{50}One {100}two {50}three");
+        assert_eq!(secs(&event.end), 12.0);
+
+        // the first word comes late, the gap is a syllable of its own
+        let subs = parse_lrc("[00:10.00]<00:11.00>Late <00:12.00>word\n[00:14.00]next\n");
+        assert_eq!(text(&subs.events[0].text.data), "{100}{100}Late {200}word");
+    }
+}
@@ -0,0 +1,239 @@
+// Lyrics kept in the song's own tags, used when there's no sidecar.
+//
+//   ID3v2 SYLT          synced, every line or syllable with its time
+//   ID3v2 USLT          plain text, or LRC text the way some taggers store it
+//   LYRICS              the same in FLAC and Ogg Vorbis comments, UNSYNCEDLYRICS too
+//
+// Synced lyrics go through the LRC reader and show up like any subtitle, plain text
+// goes to the lyrics view.
+
+use crate::libkagami::core::SubstationAlpha;
+use crate::modules::lrc::parse_lrc;
+use id3::frame::{SynchronisedLyrics, TimestampFormat};
+use std::fs::File;
+use symphonia::core::{
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+/// Vorbis comments that can hold lyrics, in the order they're tried.
+const COMMENTS: &[&str] = &["LYRICS", "UNSYNCEDLYRICS"];
+
+pub enum Lyrics {
+    Synced(SubstationAlpha),
+    Text(Vec<String>),
+}
+
+/// Plain text, unless it has LRC times.
+fn from_text(text: &str) -> Option<Lyrics> {
+    let text = text.trim_matches('\u{feff}').trim();
+    if text.is_empty() {
+        return None;
+    }
+    let synced = parse_lrc(text);
+    if !synced.events.is_empty() {
+        return Some(Lyrics::Synced(synced));
+    }
+    Some(Lyrics::Text(
+        text.lines()
+            .map(|line| line.trim_end().to_string())
+            .collect(),
+    ))
+}
+
+fn lrc_time(ms: u32) -> String {
+    format!("{:02}:{:02}.{:03}", ms / 60_000, ms / 1000 % 60, ms % 1000)
+}
+
+/// A SYLT frame as LRC text. Without line breaks every segment is a line, with them
+/// the segments are syllables and a break before or after one ends the line.
+fn sylt_lrc(sylt: &SynchronisedLyrics) -> Option<String> {
+    // MPEG frame times need the frame rate, taggers don't write them anyway
+    if sylt.timestamp_format != TimestampFormat::Ms {
+        return None;
+    }
+    let breaks = sylt
+        .content
+        .iter()
+        .any(|(_, text)| text.contains(['\n', '\r']));
+    let mut lines: Vec<(u32, String)> = Vec::new();
+    let mut open = false;
+    for (ms, text) in &sylt.content {
+        if !breaks || !open || text.starts_with(['\n', '\r']) {
+            lines.push((*ms, String::new()));
+        }
+        let line = &mut lines.last_mut().unwrap().1;
+        if breaks {
+            *line += &format!("<{}>", lrc_time(*ms));
+        }
+        *line += text.trim_matches(['\n', '\r']);
+        open = !text.ends_with(['\n', '\r']);
+    }
+    let lrc: Vec<String> = lines
+        .iter()
+        .map(|(ms, text)| format!("[{}]{}", lrc_time(*ms), text))
+        .collect();
+    (!lrc.is_empty()).then(|| lrc.join("\n"))
+}
+
+/// The first synced lyrics, or the first plain ones if none are synced.
+fn pick(found: impl IntoIterator<Item = Lyrics>) -> Option<Lyrics> {
+    let mut text = None;
+    for lyrics in found {
+        match lyrics {
+            Lyrics::Synced(_) => return Some(lyrics),
+            Lyrics::Text(_) => {
+                text.get_or_insert(lyrics);
+            }
+        }
+    }
+    text
+}
+
+fn from_id3(tag: &id3::Tag) -> Option<Lyrics> {
+    let synced = tag
+        .synchronised_lyrics()
+        .filter_map(sylt_lrc)
+        .map(|lrc| Lyrics::Synced(parse_lrc(&lrc)));
+    let text = tag.lyrics().filter_map(|uslt| from_text(&uslt.text));
+    pick(synced.chain(text))
+}
+
+fn from_comments(comments: &[(String, String)]) -> Option<Lyrics> {
+    pick(COMMENTS.iter().flat_map(|name| {
+        comments
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .filter_map(|(_, value)| from_text(value))
+    }))
+}
+
+/// Vorbis comments of an Ogg file, symphonia reads those while probing.
+fn ogg_comments(path: &str, ext: &str) -> Option<Vec<(String, String)>> {
+    let file = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(ext);
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+    let mut comments = Vec::new();
+    let mut collect = |tags: &[symphonia::core::meta::Tag]| {
+        comments.extend(
+            tags.iter()
+                .map(|tag| (tag.key.clone(), tag.value.to_string())),
+        )
+    };
+    if let Some(metadata) = probed.metadata.get()
+        && let Some(revision) = metadata.current()
+    {
+        collect(revision.tags());
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        collect(revision.tags());
+    }
+    Some(comments)
+}
+
+/// Lyrics in the tags of the file, synced ones first. None if there are none or the
+/// tags can't be read.
+pub fn read_embedded(path: &str) -> Option<Lyrics> {
+    let ext = path.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+    match ext.as_str() {
+        "flac" => {
+            let tag = metaflac::Tag::read_from_path(path).ok()?;
+            let comments: Vec<(String, String)> = tag
+                .vorbis_comments()?
+                .comments
+                .iter()
+                .flat_map(|(key, values)| values.iter().map(|v| (key.clone(), v.clone())))
+                .collect();
+            from_comments(&comments)
+        }
+        "ogg" | "oga" | "opus" => from_comments(&ogg_comments(path, &ext)?),
+        _ => from_id3(&id3::Tag::read_from_path(path).ok()?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libkagami::complex::overrides::ASSOverride;
+    use crate::libkagami::tags::ASSText;
+    use id3::TagLike;
+    use id3::frame::{Lyrics as Uslt, SynchronisedLyricsType};
+
+    fn sylt(content: &[(u32, &str)]) -> SynchronisedLyrics {
+        SynchronisedLyrics {
+            lang: "eng".to_string(),
+            timestamp_format: TimestampFormat::Ms,
+            content_type: SynchronisedLyricsType::Lyrics,
+            description: String::new(),
+            content: content.iter().map(|(ms, t)| (*ms, t.to_string())).collect(),
+        }
+    }
+
+    fn syllables(subs: &SubstationAlpha) -> Vec<usize> {
+        subs.events
+            .iter()
+            .map(|e| {
+                e.text
+                    .data
+                    .iter()
+                    .filter(|t| matches!(t, ASSText::Override(ASSOverride::K(_))))
+                    .count()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sylt_lines_and_syllables() {
+        let lines = sylt(&[(1000, "First line"), (62_500, "Second line")]);
+        assert_eq!(
+            sylt_lrc(&lines).unwrap(),
+            "[00:01.000]First line\n[01:02.500]Second line"
+        );
+
+        let words = sylt(&[(1000, "One "), (1500, "two"), (2000, "\nThree")]);
+        let lrc = sylt_lrc(&words).unwrap();
+        assert_eq!(
+            lrc,
+            "[00:01.000]<00:01.000>One <00:01.500>two\n[00:02.000]<00:02.000>Three"
+        );
+        assert_eq!(syllables(&parse_lrc(&lrc)), [2, 1]);
+
+        let frames = SynchronisedLyrics {
+            timestamp_format: TimestampFormat::Mpeg,
+            ..lines
+        };
+        assert!(sylt_lrc(&frames).is_none());
+    }
+
+    #[test]
+    fn synced_lyrics_win_over_text() {
+        let mut tag = id3::Tag::new();
+        tag.add_frame(Uslt {
+            lang: "eng".to_string(),
+            description: String::new(),
+            text: "Just\nwords\n".to_string(),
+        });
+        match from_id3(&tag) {
+            Some(Lyrics::Text(lines)) => assert_eq!(lines, ["Just", "words"]),
+            _ => panic!("expected plain lyrics"),
+        }
+        tag.add_frame(sylt(&[(1000, "Timed")]));
+        assert!(matches!(from_id3(&tag), Some(Lyrics::Synced(_))));
+
+        // LRC pasted into an unsynced field is still synced
+        let comments = [
+            ("unsyncedlyrics".to_string(), "Plain".to_string()),
+            ("Lyrics".to_string(), "[00:01.00]Timed".to_string()),
+        ];
+        assert!(matches!(from_comments(&comments), Some(Lyrics::Synced(_))));
+        assert!(from_comments(&[("TITLE".to_string(), "x".to_string())]).is_none());
+    }
+}
@@ -9,4 +9,14 @@ pub mod tui_ir;
 pub mod mouse;
 #[cfg(not(target_os = "windows"))]
 pub mod dbus;
-pub mod subtitle;
\ No newline at end of file
+pub mod subtitle;
+pub mod loudness;
+pub mod keymap;
+pub mod config;
+pub mod library;
+pub mod playlists;
+pub mod query;
+pub mod fuzzy;
+pub mod lrc;
+pub mod lyrics;
+pub mod srt;
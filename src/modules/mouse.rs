@@ -1,14 +1,13 @@
-use super::crystal_manager::{LOOP, PLAY, SHUFFLE};
-use crate::modules::crystal_manager::RESUME;
-use crate::modules::crystal_manager::STOP;
 #[cfg(feature = "mouse")]
 use crate::modules::curses::Ownership;
 use crate::modules::curses::draw_rpc_indc;
 use crate::modules::general::Action;
 use crate::modules::general::GeneralState;
+use crate::modules::keymap::KeyAction;
+#[cfg(feature = "mouse")]
+use crate::modules::songs::absolute_index;
 #[cfg(feature = "mouse")]
 use crate::modules::tui_ir::UI;
-use pancurses::Input;
 use pancurses::MEVENT;
 #[cfg(feature = "mouse")]
 #[derive(Copy, Clone)]
@@ -42,14 +41,17 @@ pub fn resolve_hit(ui: &UI<Ownership>, x: usize, y: usize) -> Option<MouseHit> {
 #[cfg(feature = "mouse")]
 pub fn hit_to_action(hit: MouseHit, general: &GeneralState) -> Action {
     match hit.owner {
-        Ownership::Songs => {
-            let row = hit.local_y;
-            Action::Play(general.index.page, row)
-        }
-
-        Ownership::SongInd => {
-            let row = hit.local_y;
-            Action::Play(general.index.page, row)
+        Ownership::Songs | Ownership::SongInd => {
+            let abs = absolute_index(
+                hit.local_y,
+                general.index.page,
+                general.songs.typical_page_size,
+            );
+            match general.rows().get(abs) {
+                Some(&id) => Action::Play(Some(id)),
+                None if abs < general.row_count() => Action::Pick(abs),
+                None => Action::Nothing,
+            }
         }
 
         Ownership::Page => {
@@ -60,6 +62,11 @@ pub fn hit_to_action(hit: MouseHit, general: &GeneralState) -> Action {
             }
         }
 
+        Ownership::Progress => {
+            let width = general.ui.get_range(&Ownership::Progress).unwrap_or(1).max(1);
+            Action::SeekTo(general.timer.maxlen * hit.local_x as u32 / width as u32)
+        }
+
         Ownership::ShuInd => Action::Shuffle,
         Ownership::LoopInd => Action::Repeat,
         Ownership::RpcInd => Action::Rpc,
@@ -81,30 +88,47 @@ pub fn handle_mouse(mevent: MEVENT, general: &GeneralState) -> Option<Action> {
     Some(hit_to_action(hit, general))
 }
 
-pub fn action_to_key(action: Action, general: &mut GeneralState) -> Option<Input> {
+pub fn action_to_key(action: Action, general: &mut GeneralState) -> Option<KeyAction> {
     match action {
-        Action::Play(page, row) => {
-            general.index.page = page;
-            general.index.index = row;
-            Some(Input::Character(PLAY))
+        Action::Play(song) => {
+            let abs = match song {
+                Some(id) => general.rows().iter().position(|&i| i == id)?,
+                None => 0,
+            };
+            general.set_cursor(abs);
+            Some(KeyAction::Play)
         }
 
-        Action::Shuffle => Some(Input::Character(SHUFFLE)),
-        Action::Repeat => Some(Input::Character(LOOP)),
+        Action::Pick(abs) => {
+            general.set_cursor(abs);
+            Some(KeyAction::Play)
+        }
+
+        Action::SeekTo(target) => {
+            general.timer.seek = Some(target);
+            Some(KeyAction::SeekPending)
+        }
+
+        Action::Shuffle => Some(KeyAction::Shuffle),
+        Action::Repeat => Some(KeyAction::Loop),
+        Action::SetRepeat(mode) => {
+            general.songs.set_repeat(mode);
+            Some(KeyAction::RepeatChanged)
+        }
         Action::Rpc => {
             general.rpc.renew();
             draw_rpc_indc(general);
             None
         }
 
-        Action::PgDown => Some(Input::KeyNPage),
-        Action::PgUp => Some(Input::KeyPPage),
+        Action::PgDown => Some(KeyAction::PageDown),
+        Action::PgUp => Some(KeyAction::PageUp),
 
-        Action::Stop => Some(Input::Character(STOP)),
-        Action::Resume => Some(Input::Character(RESUME)),
+        Action::Stop => Some(KeyAction::Stop),
+        Action::Resume => Some(KeyAction::Resume),
         
-        Action::DbusNext => Some(Input::KeyNext),
-        Action::DbusPrev => Some(Input::KeyPrevious),
+        Action::DbusNext => Some(KeyAction::Next),
+        Action::DbusPrev => Some(KeyAction::Previous),
         
         Action::Nothing => None,
     }
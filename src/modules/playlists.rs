@@ -0,0 +1,663 @@
+// Playlists are M3U files in their own directory (~/.config/neocrystal/playlists unless
+// the config says otherwise), one file per playlist named after it. They hold paths,
+// so a song can be in any number of them and its tags are never touched.
+// Files are read once at startup and written back on every change.
+// Other players' playlists come in and go out as M3U/M3U8, PLS or XSPF, see read_file()
+// and write_file(). Their entries are matched to the library by match_songs().
+
+use crate::modules::songs::Song;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Playlist {
+    pub name: String,
+    /// Absolute paths in playlist order, songs that aren't in the library are kept.
+    pub paths: Vec<String>,
+    pub file: PathBuf,
+}
+
+/// One track of a playlist file and whatever the file says about it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entry {
+    /// Absolute, relative paths and file:// URLs are resolved while reading.
+    pub path: String,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl Entry {
+    pub fn of_path(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn of_song(song: &Song) -> Self {
+        Self {
+            path: song.path.clone(),
+            artist: Some(song.artist.clone()),
+            title: Some(song.title.clone().unwrap_or_else(|| song.name.clone())),
+            duration: (!song.duration.is_zero()).then_some(song.duration),
+        }
+    }
+
+    /// "Artist - Title" the way #EXTINF and PLS titles put it.
+    pub fn label(&self) -> String {
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (None, Some(title)) => title.clone(),
+            _ => Path::new(&self.path)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+        }
+    }
+
+    /// The path, and the title if the file had one, for reports.
+    pub fn describe(&self) -> String {
+        match &self.title {
+            Some(_) => format!("{} ({})", self.path, self.label()),
+            None => self.path.clone(),
+        }
+    }
+}
+
+/// Splits "Artist - Title", a label without the separator is only a title.
+fn split_label(label: &str) -> (Option<String>, Option<String>) {
+    let label = label.trim();
+    match label.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim().into()), Some(title.trim().into())),
+        None if label.is_empty() => (None, None),
+        None => (None, Some(label.into())),
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into()
+}
+
+fn percent_encode(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// A location as playlist files write it: file:///a%20b, /abs/path or relative/to/base.
+fn resolve_location(location: &str, base: &Path) -> String {
+    match location.strip_prefix("file://") {
+        Some(url) => percent_decode(url.strip_prefix("localhost").unwrap_or(url)),
+        None => base.join(location).display().to_string(),
+    }
+}
+
+/// Entries of an M3U or M3U8 file. #EXTINF describes the path after it, other comments
+/// and #EXT lines are skipped.
+pub fn parse_m3u(buf: &str, base: &Path) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut info: Option<Entry> = None;
+    for line in buf.trim_start_matches('\u{feff}').lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:183,Artist - Title, the number can be followed by attributes
+            let (meta, label) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let seconds: Option<f64> = meta.split_whitespace().next().and_then(|s| s.parse().ok());
+            let (artist, title) = split_label(label);
+            info = Some(Entry {
+                artist,
+                title,
+                duration: seconds.filter(|s| *s > 0.0).map(Duration::from_secs_f64),
+                ..Entry::default()
+            });
+        } else if !line.is_empty() && !line.starts_with('#') {
+            entries.push(Entry {
+                path: resolve_location(line, base),
+                ..info.take().unwrap_or_default()
+            });
+        }
+    }
+    entries
+}
+
+/// Entries of a PLS file, File1= and the Title1= and Length1= that go with it.
+pub fn parse_pls(buf: &str, base: &Path) -> Vec<Entry> {
+    let mut entries: BTreeMap<u32, Entry> = BTreeMap::new();
+    for line in buf.trim_start_matches('\u{feff}').lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let field = ["file", "title", "length"]
+            .into_iter()
+            .find_map(|f| Some((f, key.strip_prefix(f)?.parse::<u32>().ok()?)));
+        let Some((field, n)) = field else {
+            continue;
+        };
+        let entry = entries.entry(n).or_default();
+        match field {
+            "file" => entry.path = resolve_location(value, base),
+            "title" => (entry.artist, entry.title) = split_label(value),
+            _ => {
+                let seconds: Option<u64> = value.parse().ok();
+                entry.duration = seconds.filter(|s| *s > 0).map(Duration::from_secs);
+            }
+        }
+    }
+    entries
+        .into_values()
+        .filter(|e| !e.path.is_empty())
+        .collect()
+}
+
+fn xml_unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out += &rest[..amp];
+        rest = &rest[amp..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => entity.strip_prefix('#').and_then(|d| d.parse().ok()),
+            }
+            .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out + rest
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// What's between every <name ...> and </name> in buf. Elements of the same name
+/// can't be nested, which XSPF doesn't do for the ones read here.
+fn xml_elements<'a>(buf: &'a str, name: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}", name), format!("</{}>", name));
+    let mut found = Vec::new();
+    let mut rest = buf;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        // <trackList> also starts with <track
+        if !rest.starts_with(['>', ' ', '\t', '\n', '\r']) {
+            continue;
+        }
+        let (Some(gt), Some(end)) = (rest.find('>'), rest.find(&close)) else {
+            break;
+        };
+        if gt < end {
+            found.push(&rest[gt + 1..end]);
+        }
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
+
+/// Entries of an XSPF file: location, creator, title and duration in milliseconds.
+pub fn parse_xspf(buf: &str, base: &Path) -> Vec<Entry> {
+    let field = |track: &str, name: &str| {
+        xml_elements(track, name)
+            .first()
+            .map(|text| xml_unescape(text.trim()))
+            .filter(|text| !text.is_empty())
+    };
+    xml_elements(buf, "track")
+        .into_iter()
+        .filter_map(|track| {
+            let ms: Option<u64> = field(track, "duration").and_then(|d| d.parse().ok());
+            Some(Entry {
+                path: resolve_location(&field(track, "location")?, base),
+                artist: field(track, "creator"),
+                title: field(track, "title"),
+                duration: ms.filter(|ms| *ms > 0).map(Duration::from_millis),
+            })
+        })
+        .collect()
+}
+
+pub fn format_m3u(entries: &[Entry]) -> String {
+    let mut buf = String::from("#EXTM3U\n");
+    for entry in entries {
+        if entry.title.is_some() {
+            let seconds = entry.duration.map_or(-1, |d| d.as_secs() as i64);
+            buf += &format!("#EXTINF:{},{}\n", seconds, entry.label());
+        }
+        buf += &entry.path;
+        buf.push('\n');
+    }
+    buf
+}
+
+pub fn format_pls(entries: &[Entry]) -> String {
+    let mut buf = String::from("[playlist]\n");
+    for (n, entry) in entries.iter().enumerate() {
+        let n = n + 1;
+        buf += &format!("File{}={}\n", n, entry.path);
+        if entry.title.is_some() {
+            buf += &format!("Title{}={}\n", n, entry.label());
+        }
+        if let Some(duration) = entry.duration {
+            buf += &format!("Length{}={}\n", n, duration.as_secs());
+        }
+    }
+    buf + &format!("NumberOfEntries={}\nVersion=2\n", entries.len())
+}
+
+pub fn format_xspf(entries: &[Entry]) -> String {
+    let mut buf = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for entry in entries {
+        buf += "    <track>\n";
+        buf += &format!(
+            "      <location>file://{}</location>\n",
+            xml_escape(&percent_encode(&entry.path))
+        );
+        if let Some(artist) = &entry.artist {
+            buf += &format!("      <creator>{}</creator>\n", xml_escape(artist));
+        }
+        if let Some(title) = &entry.title {
+            buf += &format!("      <title>{}</title>\n", xml_escape(title));
+        }
+        if let Some(duration) = entry.duration {
+            buf += &format!("      <duration>{}</duration>\n", duration.as_millis());
+        }
+        buf += "    </track>\n";
+    }
+    buf + "  </trackList>\n</playlist>\n"
+}
+
+/// The playlist file formats other players read and write.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Format {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl Format {
+    /// Goes by the extension: .m3u, .m3u8, .pls or .xspf.
+    pub fn of(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        match ext.as_str() {
+            "m3u" | "m3u8" => Some(Format::M3u),
+            "pls" => Some(Format::Pls),
+            "xspf" => Some(Format::Xspf),
+            _ => None,
+        }
+    }
+}
+
+fn unknown_format(path: &Path) -> String {
+    format!("{}: not a .m3u, .m3u8, .pls or .xspf file", path.display())
+}
+
+/// Reads a playlist file of any of the formats, relative paths in it are relative to
+/// the directory it's in.
+pub fn read_file(path: &Path) -> Result<Vec<Entry>, String> {
+    let format = Format::of(path).ok_or_else(|| unknown_format(path))?;
+    let buf = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    Ok(match format {
+        Format::M3u => parse_m3u(&buf, base),
+        Format::Pls => parse_pls(&buf, base),
+        Format::Xspf => parse_xspf(&buf, base),
+    })
+}
+
+/// Writes the entries in the format the extension asks for, with absolute paths.
+pub fn write_file(path: &Path, entries: &[Entry]) -> Result<(), String> {
+    let buf = match Format::of(path).ok_or_else(|| unknown_format(path))? {
+        Format::M3u => format_m3u(entries),
+        Format::Pls => format_pls(entries),
+        Format::Xspf => format_xspf(entries),
+    };
+    fs::write(path, buf).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn lowercase_pair(artist: &str, title: &str) -> (String, String) {
+    (artist.to_lowercase(), title.to_lowercase())
+}
+
+/// Finds the library song of every entry: by path, then by file name if only one song
+/// has it (playlists made on another machine), then by artist and title.
+/// Returns the paths of the songs found in entry order and the entries that weren't.
+pub fn match_songs<'a>(entries: &'a [Entry], songs: &[Song]) -> (Vec<String>, Vec<&'a Entry>) {
+    let by_path: HashMap<&str, &Song> = songs.iter().map(|s| (s.path.as_str(), s)).collect();
+    // None when more than one song has the name
+    let mut by_file: HashMap<&OsStr, Option<&Song>> = HashMap::new();
+    let mut by_label: HashMap<(String, String), &Song> = HashMap::new();
+    for song in songs {
+        if let Some(file) = Path::new(&song.path).file_name() {
+            by_file
+                .entry(file)
+                .and_modify(|s| *s = None)
+                .or_insert(Some(song));
+        }
+        for title in song.title.iter().chain([&song.name]) {
+            by_label
+                .entry(lowercase_pair(&song.artist, title))
+                .or_insert(song);
+        }
+    }
+    let mut found = Vec::new();
+    let mut missing = Vec::new();
+    for entry in entries {
+        let song = by_path
+            .get(entry.path.as_str())
+            .copied()
+            .or_else(|| {
+                let file = Path::new(&entry.path).file_name()?;
+                by_file.get(file).copied().flatten()
+            })
+            .or_else(|| {
+                let (artist, title) = (entry.artist.as_ref()?, entry.title.as_ref()?);
+                by_label.get(&lowercase_pair(artist, title)).copied()
+            });
+        match song {
+            Some(song) => found.push(song.path.clone()),
+            None => missing.push(entry),
+        }
+    }
+    (found, missing)
+}
+
+fn is_playlist_file(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|e| e == "m3u" || e == "m3u8")
+}
+
+/// Names become file names, so they can't be empty, hidden or contain a separator.
+fn check_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!("`{}` can't be a playlist name", name));
+    }
+    Ok(name.to_string())
+}
+
+pub struct Playlists {
+    dir: PathBuf,
+    /// Sorted by name.
+    pub lists: Vec<Playlist>,
+}
+
+impl Playlists {
+    /// Reads every playlist in dir. A missing dir is an empty list, it gets created
+    /// with the first playlist. Unreadable files are returned as "path: error".
+    pub fn load(dir: &Path) -> (Self, Vec<String>) {
+        let mut lists = Vec::new();
+        let mut problems = Vec::new();
+        if let Ok(entries) = fs::read_dir(dir) {
+            for path in entries.filter_map(Result::ok).map(|e| e.path()) {
+                if !is_playlist_file(&path) {
+                    continue;
+                }
+                match fs::read_to_string(&path) {
+                    Ok(buf) => lists.push(Playlist {
+                        name: path
+                            .file_stem()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .into(),
+                        paths: parse_m3u(&buf, dir).into_iter().map(|e| e.path).collect(),
+                        file: path,
+                    }),
+                    Err(e) => problems.push(format!("{}: {}", path.display(), e)),
+                }
+            }
+        }
+        let mut playlists = Self {
+            dir: dir.to_path_buf(),
+            lists,
+        };
+        playlists.sort();
+        (playlists, problems)
+    }
+
+    fn sort(&mut self) {
+        self.lists
+            .sort_by_key(|l| (l.name.to_lowercase(), l.name.clone()));
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Playlist> {
+        self.lists.get(index)
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.lists.iter().position(|l| l.name == name)
+    }
+
+    fn save(&self, index: usize) -> Result<(), String> {
+        let list = &self.lists[index];
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| {
+                let entries: Vec<Entry> = list.paths.iter().map(|p| Entry::of_path(p)).collect();
+                fs::write(&list.file, format_m3u(&entries))
+            })
+            .map_err(|e| format!("{}: {}", list.file.display(), e))
+    }
+
+    /// Makes a playlist of the paths and returns where it ended up in the list.
+    pub fn create(&mut self, name: &str, paths: Vec<String>) -> Result<usize, String> {
+        let name = check_name(name)?;
+        if self.find(&name).is_some() {
+            return Err(format!("there already is a playlist called `{}`", name));
+        }
+        self.lists.push(Playlist {
+            file: self.dir.join(format!("{}.m3u8", name)),
+            name: name.clone(),
+            paths,
+        });
+        let saved = self.save(self.lists.len() - 1);
+        if saved.is_err() {
+            self.lists.pop();
+        }
+        saved?;
+        self.sort();
+        Ok(self.find(&name).unwrap())
+    }
+
+    /// Renames the playlist and its file, returns where it ended up in the list.
+    pub fn rename(&mut self, index: usize, name: &str) -> Result<usize, String> {
+        let name = check_name(name)?;
+        if self.find(&name).is_some_and(|i| i != index) {
+            return Err(format!("there already is a playlist called `{}`", name));
+        }
+        let list = &mut self.lists[index];
+        let file = list.file.with_file_name(format!(
+            "{}.{}",
+            name,
+            list.file.extension().unwrap_or_default().to_string_lossy()
+        ));
+        fs::rename(&list.file, &file).map_err(|e| format!("{}: {}", list.file.display(), e))?;
+        list.file = file;
+        list.name = name.clone();
+        self.sort();
+        Ok(self.find(&name).unwrap())
+    }
+
+    /// Removes the playlist file, the songs stay where they are.
+    pub fn delete(&mut self, index: usize) -> Result<Playlist, String> {
+        let file = &self.lists[index].file;
+        fs::remove_file(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+        Ok(self.lists.remove(index))
+    }
+
+    /// Appends the song unless it's already in there, returns whether it was added.
+    pub fn add(&mut self, index: usize, path: &str) -> Result<bool, String> {
+        if self.lists[index].paths.iter().any(|p| p == path) {
+            return Ok(false);
+        }
+        self.lists[index].paths.push(path.to_string());
+        self.save(index).map(|_| true)
+    }
+
+    pub fn remove(&mut self, index: usize, path: &str) -> Result<(), String> {
+        self.lists[index].paths.retain(|p| p != path);
+        self.save(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::library::Tags;
+    use crate::modules::songs::Songs;
+    use crate::modules::songs::tests::library_song;
+
+    #[test]
+    fn m3u_entries() {
+        let buf = "\u{feff}#EXTM3U\n#EXTINF:183,Band - Song\nsong.mp3\n\n/abs/other.flac\r\n";
+        let entries = parse_m3u(buf, Path::new("/lists"));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "/lists/song.mp3");
+        assert_eq!(entries[0].artist.as_deref(), Some("Band"));
+        assert_eq!(entries[0].title.as_deref(), Some("Song"));
+        assert_eq!(entries[0].duration, Some(Duration::from_secs(183)));
+        assert_eq!(entries[1], Entry::of_path("/abs/other.flac"));
+        assert_eq!(parse_m3u(&format_m3u(&entries), Path::new("/")), entries);
+    }
+
+    #[test]
+    fn pls_and_xspf_entries() {
+        let pls = "[playlist]\nFile2=/m/b.ogg\nFile1=a.mp3\nTitle1=Band - A\nLength1=61\n\
+                   NumberOfEntries=2\nVersion=2\n";
+        let entries = parse_pls(pls, Path::new("/m"));
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/m/a.mp3", "/m/b.ogg"]);
+        assert_eq!(entries[0].title.as_deref(), Some("A"));
+        assert_eq!(parse_pls(&format_pls(&entries), Path::new("/")), entries);
+
+        let xspf = "<?xml version=\"1.0\"?><playlist><trackList>\
+                    <track><location>file:///m/R%26B%20mix.mp3</location>\
+                    <creator>Tom &amp; Jerry</creator><title>Chase</title>\
+                    <duration>61000</duration></track>\
+                    <track><title>no location</title></track>\
+                    <track>\n<location>rel.flac</location></track>\
+                    </trackList></playlist>";
+        let entries = parse_xspf(xspf, Path::new("/lists"));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "/m/R&B mix.mp3");
+        assert_eq!(entries[0].artist.as_deref(), Some("Tom & Jerry"));
+        assert_eq!(entries[0].duration, Some(Duration::from_secs(61)));
+        assert_eq!(entries[1].path, "/lists/rel.flac");
+        assert_eq!(parse_xspf(&format_xspf(&entries), Path::new("/")), entries);
+    }
+
+    #[test]
+    fn entries_match_library_songs() {
+        let song = |path: &str, title: Option<&str>| {
+            let tags = Tags {
+                artist: Some("Band".to_string()),
+                title: title.map(str::to_string),
+                ..Tags::default()
+            };
+            library_song(path, tags)
+        };
+        let mut songs = Songs::new();
+        songs.add(vec![
+            song("/m/Band/one.mp3", None),
+            song("/m/Band/two.mp3", Some("Second")),
+            song("/m/a/same.mp3", None),
+            song("/m/b/same.mp3", None),
+        ]);
+        let entry = |path: &str, artist: Option<&str>, title: Option<&str>| Entry {
+            path: path.to_string(),
+            artist: artist.map(str::to_string),
+            title: title.map(str::to_string),
+            duration: None,
+        };
+        let entries = [
+            entry("/m/Band/one.mp3", None, None),
+            entry("C:/Music/two.mp3", None, None),
+            entry("/gone/x.mp3", Some("band"), Some("second")),
+            entry("/other/same.mp3", None, None),
+            entry("/gone/y.mp3", Some("Band"), Some("Nope")),
+        ];
+        let (found, missing) = match_songs(&entries, &songs.all_songs);
+        assert_eq!(
+            found,
+            ["/m/Band/one.mp3", "/m/Band/two.mp3", "/m/Band/two.mp3"]
+        );
+        let missing: Vec<&str> = missing.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(missing, ["/other/same.mp3", "/gone/y.mp3"]);
+    }
+
+    #[test]
+    fn playlists_live_in_files() {
+        let dir = std::env::temp_dir().join(format!("neocrystal-lists-{}", std::process::id()));
+        let (mut lists, problems) = Playlists::load(&dir);
+        assert!(problems.is_empty() && lists.lists.is_empty());
+
+        assert_eq!(lists.create("road", Vec::new()), Ok(0));
+        assert_eq!(lists.create("Night", Vec::new()), Ok(0));
+        assert!(lists.create("road", Vec::new()).is_err());
+        assert!(lists.create("../up", Vec::new()).is_err());
+        assert_eq!(lists.add(1, "/m/a.mp3"), Ok(true));
+        assert_eq!(lists.add(1, "/m/a.mp3"), Ok(false));
+        assert_eq!(lists.rename(1, "Drive"), Ok(0));
+
+        let (mut back, _) = Playlists::load(&dir);
+        let names: Vec<&str> = back.lists.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Drive", "Night"]);
+        assert_eq!(back.get(0).unwrap().paths, ["/m/a.mp3"]);
+        back.delete(0).unwrap();
+        assert_eq!(Playlists::load(&dir).0.lists.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
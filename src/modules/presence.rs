@@ -17,27 +17,34 @@ use discord_presence::{models::ActivityType, Client};
 
 pub struct RpcCommunication {
     #[cfg(feature = "rpc")]
-    sender: Sender<RpcCommand>
+    sender: Option<Sender<RpcCommand>>
 }
 
 impl RpcCommunication {
-    pub fn new() -> (Self, Option<Receiver<RpcCommand>>) {
+    /// Disabled presence gets no handler thread, messages are dropped.
+    pub fn new(enabled: bool) -> (Self, Option<Receiver<RpcCommand>>) {
         #[cfg(feature = "rpc")]
         {
+            if !enabled {
+                return (Self { sender: None }, None);
+            }
             let (sender, receiver): (Sender<RpcCommand>, Receiver<RpcCommand>) = mpsc::channel();
             (Self {
-                sender: sender
+                sender: Some(sender)
             }, Some(receiver))
         }
         #[cfg(not(feature = "rpc"))]
         {
+            let _ = enabled;
             (Self {
             }, None)
         }
     }
     pub fn send_message(&self, _command: RpcCommand) {
         #[cfg(feature = "rpc")]
-        self.sender.send(_command).unwrap();
+        if let Some(sender) = &self.sender {
+            sender.send(_command).unwrap();
+        }
     }
 }
 // #[cfg(feature = "rpc")]
@@ -0,0 +1,536 @@
+// Search queries like `artist:foo album:"bar baz" year:>2010 -genre:metal dur:<3:00`.
+// Words next to each other all have to match, OR (or |) between them means either,
+// NOT (or a leading - or !) turns a match around and parentheses group.
+// Words without a field look at name, title, artist, album, album artist, genre and composer.
+// They match fuzzily and decide the order, quoted words and words under NOT have to
+// show up as they are.
+
+use crate::modules::fuzzy::{self, fold_str};
+use crate::modules::songs::Song;
+use std::fmt;
+
+/// What a song has to be like to show up, parsed from the search prompt.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Query {
+    All,
+    /// Folded, the characters in order anywhere in the song's searchable text.
+    Fuzzy(Vec<char>),
+    /// Folded, anywhere in the song's searchable text.
+    Text(String),
+    Field(Field, Test),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Field {
+    Artist,
+    Album,
+    AlbumArtist,
+    /// The title tag, or the file name without one.
+    Title,
+    Name,
+    Genre,
+    Composer,
+    Comment,
+    Path,
+    Year,
+    Track,
+    Disc,
+    /// In seconds.
+    Duration,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("artist", Field::Artist),
+    ("album", Field::Album),
+    ("albumartist", Field::AlbumArtist),
+    ("aa", Field::AlbumArtist),
+    ("title", Field::Title),
+    ("name", Field::Name),
+    ("file", Field::Name),
+    ("genre", Field::Genre),
+    ("composer", Field::Composer),
+    ("comment", Field::Comment),
+    ("path", Field::Path),
+    ("year", Field::Year),
+    ("track", Field::Track),
+    ("disc", Field::Disc),
+    ("dur", Field::Duration),
+    ("duration", Field::Duration),
+    ("length", Field::Duration),
+];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Test {
+    /// Folded, anywhere in the field.
+    Contains(String),
+    /// Folded, the whole field, written as field:=value.
+    Is(String),
+    Compare(Cmp, i64),
+}
+
+impl Field {
+    fn is_number(self) -> bool {
+        matches!(
+            self,
+            Field::Year | Field::Track | Field::Disc | Field::Duration
+        )
+    }
+
+    fn text(self, song: &Song) -> Option<&str> {
+        match self {
+            Field::Artist => Some(&song.artist),
+            Field::Album => Some(&song.album),
+            Field::AlbumArtist => song.album_artist.as_deref(),
+            Field::Title => Some(song.title.as_deref().unwrap_or(&song.name)),
+            Field::Name => Some(&song.name),
+            Field::Genre => song.genre.as_deref(),
+            Field::Composer => song.composer.as_deref(),
+            Field::Comment => song.comment.as_deref(),
+            Field::Path => Some(&song.path),
+            _ => None,
+        }
+    }
+
+    fn number(self, song: &Song) -> Option<i64> {
+        match self {
+            Field::Year => song.year.map(i64::from),
+            Field::Track => song.track.map(i64::from),
+            Field::Disc => song.disc.map(i64::from),
+            Field::Duration => Some(song.duration.as_secs() as i64),
+            _ => None,
+        }
+    }
+}
+
+impl Cmp {
+    fn holds(self, left: i64, right: i64) -> bool {
+        match self {
+            Cmp::Lt => left < right,
+            Cmp::Le => left <= right,
+            Cmp::Eq => left == right,
+            Cmp::Ge => left >= right,
+            Cmp::Gt => left > right,
+        }
+    }
+}
+
+impl Query {
+    /// How well the song matches, None if it doesn't. Fuzzy words add up, everything
+    /// else scores 0.
+    pub fn score(&self, song: &Song) -> Option<i64> {
+        match self {
+            Query::Fuzzy(pattern) => fuzzy::find(pattern, &song.searchable).map(|(s, _)| s),
+            Query::Not(query) => (!query.matches(song)).then_some(0),
+            Query::And(queries) => queries.iter().map(|q| q.score(song)).sum(),
+            Query::Or(queries) => queries.iter().filter_map(|q| q.score(song)).max(),
+            _ => self.matches(song).then_some(0),
+        }
+    }
+
+    /// Whether the song matches with fuzzy words taken as they are.
+    pub fn matches(&self, song: &Song) -> bool {
+        match self {
+            Query::All => true,
+            Query::Fuzzy(pattern) => song
+                .searchable
+                .contains(&pattern.iter().collect::<String>()),
+            Query::Text(text) => song.searchable.contains(text),
+            Query::Field(field, test) => match test {
+                Test::Contains(text) => {
+                    field.text(song).is_some_and(|f| fold_str(f).contains(text))
+                }
+                Test::Is(text) => field.text(song).is_some_and(|f| fold_str(f) == *text),
+                Test::Compare(cmp, n) => field.number(song).is_some_and(|f| cmp.holds(f, *n)),
+            },
+            Query::Not(query) => !query.matches(song),
+            Query::And(queries) => queries.iter().all(|q| q.matches(song)),
+            Query::Or(queries) => queries.iter().any(|q| q.matches(song)),
+        }
+    }
+
+    /// Char positions of label the words of the query match, for the list to show.
+    pub fn highlights(&self, label: &str) -> Vec<usize> {
+        match self {
+            Query::Fuzzy(pattern) => fuzzy::find(pattern, label).map_or(Vec::new(), |(_, p)| p),
+            Query::Text(text) => {
+                let folded: Vec<char> = label.chars().map(fuzzy::fold).collect();
+                let text: Vec<char> = text.chars().collect();
+                let mut found = Vec::new();
+                if !text.is_empty() {
+                    for start in 0..folded.len().saturating_sub(text.len() - 1) {
+                        if folded[start..start + text.len()] == text[..] {
+                            found.extend(start..start + text.len());
+                        }
+                    }
+                }
+                found
+            }
+            Query::And(queries) | Query::Or(queries) => {
+                let mut found: Vec<usize> =
+                    queries.iter().flat_map(|q| q.highlights(label)).collect();
+                found.sort_unstable();
+                found.dedup();
+                found
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// What's wrong with a query and where, `at` counts characters from 0.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParseError {
+    pub at: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (col {})", self.message, self.at + 1)
+    }
+}
+
+fn error<T>(at: usize, message: impl Into<String>) -> Result<T, ParseError> {
+    Err(ParseError {
+        at,
+        message: message.into(),
+    })
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    /// field:value or a bare value. quoted is whether the value started with a quote,
+    /// which makes = and < plain text.
+    Word {
+        field: Option<String>,
+        value: String,
+        quoted: bool,
+    },
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' | ')' | '|' | '&' => {
+                tokens.push((
+                    match chars[i] {
+                        '(' => Token::Open,
+                        ')' => Token::Close,
+                        '|' => Token::Or,
+                        _ => Token::And,
+                    },
+                    start,
+                ));
+                i += 1;
+                continue;
+            }
+            '-' | '!' if chars.get(i + 1).is_some_and(|c| !c.is_whitespace()) => {
+                tokens.push((Token::Not, start));
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        // a word, quoted parts can hold spaces and parentheses
+        let mut text = String::new();
+        let mut field = None;
+        let mut quoted = false;
+        let mut any_quote = false;
+        while let Some(&c) = chars.get(i) {
+            if c.is_whitespace() || c == '(' || c == ')' {
+                break;
+            }
+            if c == '"' {
+                let Some(len) = chars[i + 1..].iter().position(|&c| c == '"') else {
+                    return error(i, "unclosed \"");
+                };
+                quoted |= text.is_empty();
+                any_quote = true;
+                text.extend(&chars[i + 1..i + 1 + len]);
+                i += len + 2;
+                continue;
+            }
+            if c == ':' && field.is_none() && !any_quote {
+                field = Some(std::mem::take(&mut text));
+            } else {
+                text.push(c);
+            }
+            i += 1;
+        }
+        let token = match text.as_str() {
+            "AND" if field.is_none() && !any_quote => Token::And,
+            "OR" if field.is_none() && !any_quote => Token::Or,
+            "NOT" if field.is_none() && !any_quote => Token::Not,
+            _ => Token::Word {
+                field,
+                value: text,
+                quoted,
+            },
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+/// "3:00", "1:02:03" or plain seconds.
+fn parse_duration(text: &str) -> Option<i64> {
+    text.split(':').try_fold(0i64, |total, part| {
+        let n: i64 = part.parse().ok()?;
+        if part.len() > 2 && total != 0 {
+            return None;
+        }
+        total.checked_mul(60)?.checked_add(n)
+    })
+}
+
+fn parse_word(
+    field: Option<String>,
+    value: String,
+    quoted: bool,
+    at: usize,
+) -> Result<Query, ParseError> {
+    let Some(name) = field else {
+        return Ok(match quoted {
+            true => Query::Text(fold_str(&value)),
+            false => Query::Fuzzy(value.chars().map(fuzzy::fold).collect()),
+        });
+    };
+    let Some(&(_, field)) = FIELDS.iter().find(|(n, _)| n.eq_ignore_ascii_case(&name)) else {
+        return error(at, format!("unknown field `{}`", name));
+    };
+    if value.is_empty() {
+        return error(at, format!("`{}:` needs a value", name));
+    }
+    if !field.is_number() {
+        return Ok(Query::Field(
+            field,
+            match value.strip_prefix('=').filter(|_| !quoted) {
+                Some(exact) => Test::Is(fold_str(exact)),
+                None => Test::Contains(fold_str(&value)),
+            },
+        ));
+    }
+    let (cmp, number) = [
+        (">=", Cmp::Ge),
+        ("<=", Cmp::Le),
+        (">", Cmp::Gt),
+        ("<", Cmp::Lt),
+        ("=", Cmp::Eq),
+    ]
+    .into_iter()
+    .find_map(|(op, cmp)| Some((cmp, value.strip_prefix(op)?)))
+    .unwrap_or((Cmp::Eq, &value));
+    let parsed = match field {
+        Field::Duration => parse_duration(number),
+        _ => number.parse().ok(),
+    };
+    match parsed {
+        Some(n) => Ok(Query::Field(field, Test::Compare(cmp, n))),
+        None if field == Field::Duration => {
+            error(at, format!("`{}` isn't a duration like 3:30", number))
+        }
+        None => error(at, format!("`{}` isn't a number", number)),
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Where the input ends, for errors about something missing.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn at(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |&(_, at)| at)
+    }
+
+    fn or(&mut self) -> Result<Query, ParseError> {
+        let mut any = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            any.push(self.and()?);
+        }
+        Ok(match any.len() {
+            1 => any.remove(0),
+            _ => Query::Or(any),
+        })
+    }
+
+    fn and(&mut self) -> Result<Query, ParseError> {
+        let mut all = vec![self.unary()?];
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(Token::And) => self.pos += 1,
+                _ => {}
+            }
+            all.push(self.unary()?);
+        }
+        Ok(match all.len() {
+            1 => all.remove(0),
+            _ => Query::And(all),
+        })
+    }
+
+    fn unary(&mut self) -> Result<Query, ParseError> {
+        let at = self.at();
+        let Some((token, _)) = self.tokens.get(self.pos).cloned() else {
+            return error(at, "something to search for is missing at the end");
+        };
+        self.pos += 1;
+        match token {
+            Token::Not => Ok(Query::Not(Box::new(self.unary()?))),
+            Token::Open => {
+                let inner = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return error(at, "unclosed (");
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Token::Word {
+                field,
+                value,
+                quoted,
+            } => parse_word(field, value, quoted, at),
+            Token::Close => error(at, "unmatched )"),
+            Token::And | Token::Or => error(at, "AND and OR go between two searches"),
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Query, ParseError> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Ok(Query::All);
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: input.chars().count(),
+    };
+    let query = parser.or()?;
+    match parser.peek() {
+        None => Ok(query),
+        // or() only stops early on a )
+        Some(_) => error(parser.at(), "unmatched )"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::library::{Probed, Tags};
+    use crate::modules::songs::Songs;
+    use crate::modules::songs::tests::library_song;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_into_a_tree() {
+        let text = |t: &str| Query::Text(t.to_string());
+        let fuzzy = |t: &str| Query::Fuzzy(t.chars().collect());
+        assert_eq!(parse("  "), Ok(Query::All));
+        assert_eq!(
+            parse(r#"Foo artist:"Bar Baz" year:>2010"#),
+            Ok(Query::And(vec![
+                fuzzy("foo"),
+                Query::Field(Field::Artist, Test::Contains("bar baz".into())),
+                Query::Field(Field::Year, Test::Compare(Cmp::Gt, 2010)),
+            ]))
+        );
+        assert_eq!(
+            parse("a OR b c | -(d)"),
+            Ok(Query::Or(vec![
+                fuzzy("a"),
+                Query::And(vec![fuzzy("b"), fuzzy("c")]),
+                Query::Not(Box::new(fuzzy("d"))),
+            ]))
+        );
+        assert_eq!(
+            parse(r#"dur:<3:00 "re: or" title:=x"#),
+            Ok(Query::And(vec![
+                Query::Field(Field::Duration, Test::Compare(Cmp::Lt, 180)),
+                text("re: or"),
+                Query::Field(Field::Title, Test::Is("x".into())),
+            ]))
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let at = |q: &str| parse(q).map_err(|e| e.at);
+        assert_eq!(at("a \"b"), Err(2));
+        assert_eq!(at("foo:bar"), Err(0));
+        assert_eq!(at("x year:abc"), Err(2));
+        assert_eq!(at("dur:3:0:0:0x"), Err(0));
+        let long = parse("dur:1:00:00:00:00:00:00:00:00:00:00:00").unwrap_err();
+        assert_eq!(long.at, 0);
+        assert!(long.message.contains("isn't a duration"));
+        assert_eq!(at("(a b"), Err(0));
+        assert_eq!(at("a )"), Err(2));
+        assert_eq!(at("a OR"), Err(4));
+        assert_eq!(at("artist:"), Err(0));
+    }
+
+    #[test]
+    fn matches_song_fields() {
+        let mut songs = Songs::new();
+        let tags = Tags {
+            artist: Some("The Band".into()),
+            album: Some("Bar Baz".into()),
+            genre: Some("Metal".into()),
+            year: Some(2012),
+            ..Tags::default()
+        };
+        let (file, probed) = library_song("/m/Band/Album/01 Song.flac", tags);
+        let probed = Probed {
+            duration: Duration::from_secs(170),
+            ..probed
+        };
+        songs.add(vec![(file, probed)]);
+        let song = &songs.all_songs[0];
+        let yes = |q: &str| parse(q).unwrap().score(song).is_some();
+        assert!(yes(r#"artist:band album:"bar baz" year:>2010 dur:<3:00"#));
+        assert!(!yes("artist:band -genre:metal"));
+        assert!(yes("genre:jazz OR year:2012"));
+        assert!(yes("artist:=\"the band\" song"));
+        assert!(!yes("artist:=band"));
+        assert!(!yes("track:1"));
+        assert!(yes("thbnd -bnd"));
+        assert!(!yes("\"thbnd\""));
+        assert_eq!(
+            parse("bnd \"song\"").unwrap().highlights("01 Song Band"),
+            [3, 4, 5, 6, 8, 10, 11]
+        );
+    }
+}
@@ -1,124 +1,463 @@
-use crate::modules::utils::{addto_album, album_data};
-use rand::seq::SliceRandom;
+use crate::modules::utils::addto_album;
+use home::home_dir;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::{IndexedRandom, SliceRandom};
 
-use super::utils::{artist_data, change_artist};
-use std::path::Path;
-use std::thread::spawn;
-use std::time::Duration;
-use super::audio::audio_duration;
+use super::utils::change_artist;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use super::library::{LibraryFile, Probed};
+use super::loudness::{GainMode, ReplayGain};
+use super::playlists::Playlist;
+use super::fuzzy::fold_str;
+use super::query::{self, ParseError, Query};
+
+/// Identifies a song for as long as it stays in the library, unlike its position
+/// in all_songs which moves whenever songs are added or removed.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
+pub struct SongId(u64);
+
+impl std::fmt::Display for SongId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// How the next song is picked once the queue is empty.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ShuffleMode {
+    Off,
+    /// Every song once per round, in random order.
+    Bag,
+    /// Albums in random order, the songs of an album in list order.
+    Album,
+    /// Every pick is random, songs that were played more come up more.
+    Weighted,
+}
+
+impl ShuffleMode {
+    pub fn cycle(self) -> Self {
+        match self {
+            ShuffleMode::Off => ShuffleMode::Bag,
+            ShuffleMode::Bag => ShuffleMode::Album,
+            ShuffleMode::Album => ShuffleMode::Weighted,
+            ShuffleMode::Weighted => ShuffleMode::Off,
+        }
+    }
+    pub fn label(self) -> &'static str {
+        match self {
+            ShuffleMode::Off => "no",
+            ShuffleMode::Bag => "bag",
+            ShuffleMode::Album => "alb",
+            ShuffleMode::Weighted => "wgt",
+        }
+    }
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(ShuffleMode::Off),
+            "bag" => Some(ShuffleMode::Bag),
+            "album" => Some(ShuffleMode::Album),
+            "weighted" => Some(ShuffleMode::Weighted),
+            _ => None,
+        }
+    }
+}
+
+/// What happens when a song ends.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RepeatMode {
+    /// Playback stops after the last song of the list or the round.
+    Off,
+    One,
+    All,
+}
+
+impl RepeatMode {
+    pub fn cycle(self) -> Self {
+        match self {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        }
+    }
+    pub fn label(self) -> &'static str {
+        match self {
+            RepeatMode::Off => "no",
+            RepeatMode::One => "one",
+            RepeatMode::All => "all",
+        }
+    }
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(RepeatMode::Off),
+            "one" => Some(RepeatMode::One),
+            "all" => Some(RepeatMode::All),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Song {
+    pub id: SongId,
     pub path: String,
+    /// The file name without its extension.
     pub name: String,
+    /// The title tag, shown instead of name when tag_titles is on.
+    pub title: Option<String>,
     pub artist: String,
-    pub playlist: String,
+    pub album: String,
+    pub album_artist: Option<String>,
+    pub track: Option<u16>,
+    pub disc: Option<u16>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
     pub searchable: String,
     pub duration: Duration,
-    pub forced: bool,
+    pub gain: ReplayGain,
+}
+
+impl Song {
+    /// Tags win, the directories the file sits in are the fallback.
+    pub fn new(id: SongId, file: LibraryFile, probed: Probed) -> Self {
+        let name = Path::new(&file.path)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let tags = probed.tags;
+        let artist = tags
+            .artist
+            .or(file.artist)
+            .unwrap_or_else(|| "Unknown".to_string());
+        let album = tags.album.or(file.album).unwrap_or_default();
+        let mut song = Self {
+            id,
+            path: file.path,
+            name,
+            title: tags.title,
+            artist,
+            album,
+            album_artist: tags.album_artist,
+            track: tags.track,
+            disc: tags.disc,
+            year: tags.year,
+            genre: tags.genre,
+            composer: tags.composer,
+            comment: tags.comment,
+            searchable: String::new(),
+            duration: probed.duration,
+            gain: probed.gain,
+        };
+        song.update_searchable();
+        song
+    }
+
+    /// Has to be called after the name, title or any of the text tags change.
+    fn update_searchable(&mut self) {
+        let optional = [&self.title, &self.album_artist, &self.genre, &self.composer];
+        let mut fields = vec![self.name.as_str(), &self.artist, &self.album];
+        fields.extend(optional.into_iter().flatten().map(String::as_str));
+        self.searchable = fold_str(&fields.join(" "));
+    }
+
+    /// What the list and the title line show.
+    pub fn display_name(&self, tag_titles: bool) -> &str {
+        match &self.title {
+            Some(title) if tag_titles => title,
+            _ => &self.name,
+        }
+    }
+
+    /// Album artist and album, what makes two songs part of the same album.
+    fn album_key(&self) -> (&str, &str) {
+        (
+            self.album_artist.as_deref().unwrap_or(&self.artist),
+            &self.album,
+        )
+    }
+
+    /// Album artist, year, album, disc, track, with the file name last so songs
+    /// without tags keep a stable order.
+    fn sort_key(&self) -> (&str, Option<i32>, &str, Option<u16>, Option<u16>, &str) {
+        (
+            self.album_artist.as_deref().unwrap_or(&self.artist),
+            self.year,
+            &self.album,
+            self.disc,
+            self.track,
+            &self.name,
+        )
+    }
 }
 
 pub struct Songs {
     pub all_songs: Vec<Song>,
-    pub filtered_songs: Vec<usize>,
-    pub current_index: usize,
+    /// The playlist that is the list instead of the whole library.
+    pub source: Option<Playlist>,
+    pub filtered_songs: Vec<SongId>,
+    pub current: Option<SongId>,
     pub stophandler: bool,
-    pub shuffle: bool,
+    pub shuffle: ShuffleMode,
+    pub repeat: RepeatMode,
+    /// Show title tags instead of file names, where there are any.
+    pub tag_titles: bool,
     pub typical_page_size: usize,
-    pub blacklist: Vec<usize>,
-    pub setnext: usize,
+    pub blacklist: Vec<SongId>,
+    pub setnext: Option<SongId>,
+    /// Songs picked by hand, played in order before the algorithm gets to choose.
+    pub queue: VecDeque<SongId>,
+    /// What started playing and when, oldest first. The last entry is the current song.
+    pub history: VecDeque<(SongId, SystemTime)>,
+    /// What played since bag or album shuffle last started over.
+    round: HashSet<SongId>,
+    /// How often each path started playing, kept across runs by load_plays() and save_plays().
+    pub plays: HashMap<String, u32>,
+    rng: StdRng,
+    positions: HashMap<SongId, usize>,
+    /// The filtered songs in display order, filtered_songs is the play order.
+    ordered: Vec<SongId>,
+    next_id: u64,
+    /// The search, the list only has the songs it matches.
+    filter: Query,
+    gone: Vec<String>,
+}
+
+fn plays_file() -> Option<PathBuf> {
+    home_dir().map(|h| h.join(".cache").join("neocrystal").join("plays.tsv"))
 }
 
+/// Older songs fall out of the history.
+const HISTORY_LEN: usize = 100;
+
 #[inline]
 pub fn absolute_index(index: usize, page: usize, typical_page_size: usize) -> usize {
     index + ((page - 1) * typical_page_size)
 }
 
 impl Songs {
-    pub fn constructor(paths: Vec<String>) -> Self {
-        let mut all_songs = Vec::with_capacity(paths.len());
-        let mut durations = vec![Duration::from_secs(0); paths.len()];
-        let mut handles = Vec::new();
-
-        for (i, path) in paths.iter().enumerate() {
-            let path_clone = path.clone();
-            let handle = spawn(move || {
-                audio_duration(&path_clone)
-            });
-            handles.push((i, handle));
-        }
-
-        for (i, handle) in handles {
-            if let Ok(duration) = handle.join() {
-                durations[i] = duration;
+    /// Empty list, the library comes in later through add().
+    pub fn new() -> Self {
+        Self {
+            all_songs: Vec::new(),
+            source: None,
+            filtered_songs: Vec::new(),
+            current: None,
+            stophandler: true,
+            shuffle: ShuffleMode::Off,
+            repeat: RepeatMode::All,
+            tag_titles: false,
+            typical_page_size: 14,
+            blacklist: Vec::new(),
+            setnext: None,
+            queue: VecDeque::new(),
+            history: VecDeque::new(),
+            round: HashSet::new(),
+            plays: HashMap::new(),
+            rng: StdRng::from_os_rng(),
+            positions: HashMap::new(),
+            ordered: Vec::new(),
+            next_id: 0,
+            filter: Query::All,
+            gone: Vec::new(),
+        }
+    }
+
+    /// Reads the play counts, one "count\tpath" per line.
+    pub fn load_plays(&mut self) {
+        let Some(buf) = plays_file().and_then(|f| fs::read_to_string(f).ok()) else {
+            return;
+        };
+        for line in buf.lines() {
+            if let Some((count, path)) = line.split_once('\t')
+                && let Ok(count) = count.parse()
+            {
+                self.plays.insert(path.to_string(), count);
             }
         }
+    }
+
+    pub fn save_plays(&self) {
+        let Some(file) = plays_file() else {
+            return;
+        };
+        if let Some(parent) = file.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let buf: String = self
+            .plays
+            .iter()
+            .map(|(path, count)| format!("{}\t{}\n", count, path))
+            .collect();
+        let _ = fs::write(file, buf);
+    }
 
-        for (i, path) in paths.iter().enumerate() {
-            let artist = artist_data(path);
-            let playlist = album_data(path);
-            let name = Path::new(&path)
-                .file_stem()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
+    /// Makes every shuffle repeatable.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
 
-            let searchable = format!(
-                "{} {} {}",
-                name.to_lowercase(),
-                artist.to_lowercase(),
-                playlist.to_lowercase()
-            );
+    pub fn song(&self, id: SongId) -> Option<&Song> {
+        self.positions.get(&id).map(|&i| &self.all_songs[i])
+    }
+
+    pub fn song_mut(&mut self, id: SongId) -> Option<&mut Song> {
+        self.positions.get(&id).map(|&i| &mut self.all_songs[i])
+    }
+
+    fn current_song(&self) -> Option<&Song> {
+        self.current.and_then(|id| self.song(id))
+    }
+
+    fn reindex(&mut self) {
+        self.positions = self
+            .all_songs
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.id, i))
+            .collect();
+    }
 
-            all_songs.push(Song {
-                path: path.clone(),
-                name,
-                artist,
-                playlist,
-                searchable,
-                duration: durations[i],
-                forced: false,
-            });
+    /// Merges a batch from the library loader or watcher. Songs already in the list are
+    /// replaced but keep their id. Songs stay sorted by album artist, year, album,
+    /// disc and track.
+    pub fn add(&mut self, batch: Vec<(LibraryFile, Probed)>) {
+        if batch.is_empty() {
+            return;
+        }
+        let known: HashMap<String, usize> = self
+            .all_songs
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.path.clone(), i))
+            .collect();
+        for (file, probed) in batch {
+            match known.get(&file.path) {
+                Some(&i) => {
+                    let old = &self.all_songs[i];
+                    let analyzed = old.gain.analyzed;
+                    self.all_songs[i] = Song::new(old.id, file, probed);
+                    self.all_songs[i].gain.analyzed = self.all_songs[i].gain.analyzed.or(analyzed);
+                }
+                None => {
+                    let id = SongId(self.next_id);
+                    self.next_id += 1;
+                    self.all_songs.push(Song::new(id, file, probed));
+                }
+            }
         }
+        self.all_songs
+            .sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        self.reindex();
+        // new songs show up only if they match what's searched
+        self.refilter();
+    }
 
-        all_songs.sort_by(|a, b| (&a.artist, &a.name).cmp(&(&b.artist, &b.name)));
+    /// Drops songs whose files are gone, paths can also be directories.
+    /// The current song stays until something else plays, see sweep().
+    /// Returns true if the list changed.
+    pub fn remove(&mut self, paths: Vec<String>) -> bool {
+        self.gone.extend(paths);
+        self.sweep()
+    }
 
-        let filtered_songs = (0..all_songs.len()).collect::<Vec<_>>();
+    /// Retries removals that were held back for the current song.
+    pub fn sweep(&mut self) -> bool {
+        if self.gone.is_empty() {
+            return false;
+        }
+        let is_gone = |song: &Song| {
+            self.gone
+                .iter()
+                .any(|g| Path::new(&song.path).starts_with(g))
+        };
+        let current = self.current;
+        let removed: Vec<SongId> = self
+            .all_songs
+            .iter()
+            .filter(|s| Some(s.id) != current && is_gone(s))
+            .map(|s| s.id)
+            .collect();
+        match self.current_song().filter(|s| is_gone(s)) {
+            Some(song) => {
+                let path = song.path.clone();
+                self.gone.retain(|g| Path::new(&path).starts_with(g));
+            }
+            None => self.gone.clear(),
+        }
+        if removed.is_empty() {
+            return false;
+        }
 
-        Self {
-            all_songs,
-            filtered_songs,
-            current_index: usize::MAX,
-            stophandler: true,
-            shuffle: false,
-            typical_page_size: 14,
-            blacklist: Vec::new(),
-            setnext: usize::MAX,
+        self.all_songs.retain(|s| !removed.contains(&s.id));
+        self.reindex();
+        self.blacklist.retain(|id| !removed.contains(id));
+        self.queue.retain(|id| !removed.contains(id));
+        self.history.retain(|(id, _)| !removed.contains(id));
+        if self.setnext.is_some_and(|id| removed.contains(&id)) {
+            self.setnext = None;
         }
+        self.refilter();
+        true
     }
 
-    pub fn get_ordered(&self) -> Vec<usize> {
-        let mut nvc = self.filtered_songs.clone();
-        nvc.sort();
-        nvc
+    /// The filtered songs in display order.
+    pub fn get_ordered(&self) -> Vec<SongId> {
+        self.ordered.clone()
     }
 
-    pub fn get_unordered(&self) -> &Vec<usize> {
+    /// The filtered songs in play order.
+    pub fn get_unordered(&self) -> &Vec<SongId> {
         &self.filtered_songs
     }
 
-    fn urandom(&mut self) {
-        if self.shuffle {
-            self.filtered_songs.shuffle(&mut rand::rng());
-        } else {
-            self.filtered_songs.sort();
+    /// Lays the filtered songs out in play order for the shuffle mode.
+    fn reorder(&mut self) {
+        let mut order = self.get_ordered();
+        match self.shuffle {
+            ShuffleMode::Off | ShuffleMode::Weighted => {}
+            ShuffleMode::Bag => order.shuffle(&mut self.rng),
+            ShuffleMode::Album => {
+                // songs without an album are an album of their own, albums that share a
+                // title are told apart by their artist like in the sort order
+                let mut albums: Vec<Vec<SongId>> = Vec::new();
+                let mut seen: HashMap<(&str, &str), usize> = HashMap::new();
+                for &id in &order {
+                    let album = self.song(id).map_or(("", ""), |s| s.album_key());
+                    match seen.get(&album) {
+                        Some(&a) if !album.1.is_empty() => albums[a].push(id),
+                        _ => {
+                            seen.insert(album, albums.len());
+                            albums.push(vec![id]);
+                        }
+                    }
+                }
+                albums.shuffle(&mut self.rng);
+                order = albums.concat();
+            }
         }
+        self.filtered_songs = order;
     }
 
     pub fn shuffle(&mut self) {
-        self.shuffle = !self.shuffle;
-        self.urandom();
-        self.setnext = self.algorithm_setnext().unwrap_or(usize::MAX);
+        self.set_shuffle(self.shuffle.cycle());
+    }
+
+    pub fn set_repeat(&mut self, mode: RepeatMode) {
+        self.repeat = mode;
+        self.setnext = self.algorithm_setnext();
+    }
+
+    pub fn set_shuffle(&mut self, mode: ShuffleMode) {
+        self.shuffle = mode;
+        self.round.clear();
+        self.reorder();
+        self.setnext = self.algorithm_setnext();
     }
 
     pub fn current_artist(&self) -> String {
@@ -126,22 +465,17 @@ impl Songs {
             return "Nothing".to_string();
         }
 
-        self.all_songs
-            .get(self.current_index)
+        self.current_song()
             .map(|s| s.artist.clone())
             .unwrap_or("Nothing".to_string())
     }
 
-    pub fn current_playlist(&self) -> String {
+    pub fn current_album(&self) -> String {
         if self.stophandler {
             return " ".to_string();
         }
 
-        match self
-            .all_songs
-            .get(self.current_index)
-            .map(|s| s.playlist.clone())
-        {
+        match self.current_song().map(|s| s.album.clone()) {
             Some(something) => {
                 if something == "" {
                     " ".to_string()
@@ -155,8 +489,8 @@ impl Songs {
 
     pub fn _status(&self) -> u8 {
         if self.stophandler {
-            // current index is initialized but setnext is 0 from stop() method
-            if self.setnext == usize::MAX && !self.current_index == usize::MAX {
+            // something played before but nothing is up next since stop()
+            if self.setnext.is_none() && self.current.is_some() {
                 return 1;
             }
             return 0;
@@ -170,139 +504,288 @@ impl Songs {
             return "Nothing".to_string();
         }
 
-        self.all_songs
-            .get(self.current_index)
+        self.current_song()
             .map(|s| s.path.clone())
             .unwrap_or("Nothing".to_string())
     }
 
-    pub fn set_next(&mut self, original_index: usize) {
-        if let Some(&idx) = self.filtered_songs.get(original_index) {
-            self.setnext = idx;
-            self.all_songs[self.setnext].forced = true;
+    /// Puts the song at the front of the queue, wherever it was queued before.
+    pub fn set_next(&mut self, id: SongId) {
+        if self.blacklist.contains(&id) {
+            return;
+        }
+        self.queue.retain(|&q| q != id);
+        self.queue.push_front(id);
+        self.setnext = self.algorithm_setnext();
+    }
+
+    pub fn enqueue(&mut self, id: SongId) {
+        if self.blacklist.contains(&id) {
+            return;
+        }
+        self.queue.push_back(id);
+        self.setnext = self.algorithm_setnext();
+    }
+
+    /// Queues everything the search shows, in list order.
+    pub fn enqueue_all(&mut self) {
+        for id in self.get_ordered() {
+            if !self.blacklist.contains(&id) {
+                self.queue.push_back(id);
+            }
+        }
+        self.setnext = self.algorithm_setnext();
+    }
+
+    /// Takes every entry of the song out of the queue.
+    pub fn unqueue(&mut self, id: SongId) {
+        self.queue.retain(|&q| q != id);
+        self.setnext = self.algorithm_setnext();
+    }
+
+    pub fn dequeue(&mut self, position: usize) {
+        if self.queue.remove(position).is_some() {
+            self.setnext = self.algorithm_setnext();
+        }
+    }
+
+    /// Swaps a queue entry with its neighbour, returns where it ended up.
+    pub fn move_queued(&mut self, position: usize, up: bool) -> Option<usize> {
+        let target = if up {
+            position.checked_sub(1)?
+        } else {
+            position + 1
+        };
+        if target >= self.queue.len() {
+            return None;
         }
+        self.queue.swap(position, target);
+        self.setnext = self.algorithm_setnext();
+        Some(target)
+    }
+
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+        self.setnext = self.algorithm_setnext();
     }
 
-    pub fn get_next(&self) -> usize {
+    pub fn is_queued(&self, id: SongId) -> bool {
+        self.queue.contains(&id)
+    }
+
+    pub fn get_next(&self) -> Option<SongId> {
         self.setnext
     }
 
-    pub fn get_filtered_index(&self, original_index: usize) -> Result<usize, ()> {
-        self.get_ordered()
-            .iter()
-            .position(|&i| i == original_index)
-            .ok_or(())
+    /// Path and gain of the song that would play after the current one, for preloading.
+    pub fn next_song(&self, mode: GainMode) -> Option<(String, f32)> {
+        if self.stophandler {
+            return None;
+        }
+        self.setnext
+            .and_then(|id| self.song(id))
+            .map(|s| (s.path.clone(), s.gain.factor(mode)))
     }
 
-    pub fn match_c(&self) -> usize {
-        self.get_ordered()
+    pub fn current_gain(&self, mode: GainMode) -> f32 {
+        self.current_song()
+            .map(|s| s.gain.factor(mode))
+            .unwrap_or(1.0)
+    }
+
+    /// Songs with neither ReplayGain tags nor an analyzed gain.
+    pub fn unanalyzed_paths(&self) -> Vec<String> {
+        self.all_songs
             .iter()
-            .position(|&i| i == self.current_index)
-            .unwrap_or(usize::MAX)
+            .filter(|s| !s.gain.is_tagged() && s.gain.analyzed.is_none())
+            .map(|s| s.path.clone())
+            .collect()
+    }
+
+    /// Stores a gain from the R128 pass, returns true if it belongs to the current song.
+    pub fn set_analyzed_gain(&mut self, path: &str, gain: f32) -> bool {
+        match self.all_songs.iter_mut().find(|s| s.path == path) {
+            Some(song) => {
+                song.gain.analyzed = Some(gain);
+                Some(song.id) == self.current
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the next song comes from the queue rather than the algorithm.
+    pub fn next_is_queued(&self) -> bool {
+        self.setnext.is_some() && self.queue.front() == self.setnext.as_ref()
     }
 
-    pub fn set_artist(&mut self, index_in_filtered: usize, artist: &String) {
-        if self.stophandler || index_in_filtered >= self.filtered_songs.len() {
+    pub fn set_artist(&mut self, id: SongId, artist: &str) {
+        if self.stophandler {
             return;
         }
-        let idx = self.get_ordered()[index_in_filtered];
-        if change_artist(&self.all_songs[idx].path, artist).is_ok() {
-            self.all_songs[idx].artist = artist.clone();
-            self.all_songs[idx].searchable = self.all_songs[idx].name.clone().to_lowercase()
-                + &self.all_songs[idx].artist.to_lowercase()
-                + &self.all_songs[idx].playlist.to_lowercase();
+        let Some(song) = self.song_mut(id) else {
+            return;
+        };
+        if change_artist(&song.path, artist).is_ok() {
+            song.artist = artist.to_string();
+            song.update_searchable();
         }
     }
-    pub fn set_playlist(&mut self, index_in_filtered: usize, playlist: &String) {
-        if self.stophandler || index_in_filtered >= self.filtered_songs.len() {
+    pub fn set_album(&mut self, id: SongId, album: &str) {
+        if self.stophandler {
             return;
         }
-        let idx = self.get_ordered()[index_in_filtered];
-        if addto_album(&self.all_songs[idx].path, playlist).is_ok() {
-            self.all_songs[idx].playlist = playlist.clone();
-            self.all_songs[idx].searchable = self.all_songs[idx].name.clone().to_lowercase()
-                + &self.all_songs[idx].artist.to_lowercase()
-                + &playlist.to_string().to_lowercase();
+        let Some(song) = self.song_mut(id) else {
+            return;
+        };
+        if addto_album(&song.path, album).is_ok() {
+            song.album = album.to_string();
+            song.update_searchable();
         }
     }
-    pub fn search(&mut self, pattern: &String) {
-        if pattern == "false" || pattern.is_empty() {
-            self.filtered_songs = (0..self.all_songs.len()).collect();
-            self.setnext = self.algorithm_setnext().unwrap_or(usize::MAX);
-            return;
-        } else {
+    /// The songs of the source playlist in its order, the whole library otherwise.
+    fn source_songs(&self) -> Vec<&Song> {
+        let Some(source) = &self.source else {
+            return self.all_songs.iter().collect();
+        };
+        let by_path: HashMap<&str, &Song> = self
+            .all_songs
+            .iter()
+            .map(|s| (s.path.as_str(), s))
+            .collect();
+        source
+            .paths
+            .iter()
+            .filter_map(|p| by_path.get(p.as_str()).copied())
+            .collect()
+    }
 
-            let pattern = pattern.to_lowercase();
-            self.filtered_songs = self
-                .all_songs
-                .iter()
-                .enumerate()
-                .filter(|(_, s)| s.searchable.contains(&pattern))
-                .map(|(i, _)| i)
-                .collect();
+    /// Plays from the playlist instead of the whole library, or from the library again
+    /// with None. Starts without a search.
+    pub fn set_source(&mut self, source: Option<Playlist>) {
+        self.source = source;
+        self.round.clear();
+        self.filter = Query::All;
+        self.refilter();
+    }
 
-            self.setnext = self.algorithm_setnext().unwrap_or(usize::MAX);
-        }
-        self.urandom();
+    /// Takes the source playlist as it is now, keeping the search.
+    pub fn update_source(&mut self, source: Playlist) {
+        self.source = Some(source);
+        self.refilter();
+    }
 
+    /// Shows the songs the query matches. A query that doesn't parse leaves the list as
+    /// it was.
+    pub fn search(&mut self, pattern: &str) -> Result<(), ParseError> {
+        self.filter = match pattern {
+            "false" => Query::All,
+            _ => query::parse(pattern)?,
+        };
+        self.refilter();
+        Ok(())
     }
 
-    pub fn blacklist(&mut self, index_in_filtered: usize) {
-        if index_in_filtered >= self.filtered_songs.len() {
-            return;
-        }
+    /// Char positions of a list label the search matched.
+    pub fn highlights(&self, label: &str) -> Vec<usize> {
+        self.filter.highlights(label)
+    }
 
-        let original_index = self.get_ordered()[index_in_filtered];
+    /// Runs the search again after the songs or the source changed. Better matches come
+    /// first, songs that score the same keep their order.
+    fn refilter(&mut self) {
+        let mut scored: Vec<(i64, SongId)> = self
+            .source_songs()
+            .into_iter()
+            .filter_map(|s| Some((self.filter.score(s)?, s.id)))
+            .collect();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        self.ordered = scored.into_iter().map(|(_, id)| id).collect();
+        self.reorder();
+        self.setnext = self.algorithm_setnext();
+    }
 
-        if original_index == self.current_index {
+    pub fn blacklist(&mut self, id: SongId) {
+        if Some(id) == self.current {
             return;
         }
 
-        if let Some(pos) = self.blacklist.iter().position(|&x| x == original_index) {
+        if let Some(pos) = self.blacklist.iter().position(|&x| x == id) {
             self.blacklist.remove(pos);
-            if !self.shuffle && self.setnext > original_index && self.setnext != usize::MAX {
-                self.setnext = self.algorithm_setnext().unwrap_or(usize::MAX);
+            if self.shuffle == ShuffleMode::Off && self.setnext.is_some() {
+                self.setnext = self.algorithm_setnext();
             }
         } else {
-            self.blacklist.push(original_index);
-            if original_index == self.setnext {
-                if self.setnext != usize::MAX {
-                    self.all_songs[self.setnext].forced = false;
-                }
-                self.setnext = self.algorithm_setnext().unwrap_or(usize::MAX);
+            self.blacklist.push(id);
+            self.queue.retain(|&q| q != id);
+            if Some(id) == self.setnext {
+                self.setnext = self.algorithm_setnext();
             }
         }
     }
 
-    pub fn is_blacklist(&self, original_index: usize) -> bool {
-        self.blacklist.contains(&original_index)
+    pub fn is_blacklist(&self, id: SongId) -> bool {
+        self.blacklist.contains(&id)
     }
 
     pub fn current_name(&self) -> String {
-        self.all_songs
-            .get(self.current_index)
-            .map(|s| s.name.clone())
+        self.current_song()
+            .map(|s| s.display_name(self.tag_titles).to_string())
             .unwrap_or("Nothing".to_string())
     }
 
-    fn renew_current_status(&mut self, original_index: usize) {
-        self.current_index = original_index;
-        self.all_songs[original_index].forced = false;
+    fn renew_current_status(&mut self, id: SongId) {
+        self.current = Some(id);
+        self.round.insert(id);
+        if let Some(song) = self.song(id) {
+            *self.plays.entry(song.path.clone()).or_default() += 1;
+        }
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back((id, SystemTime::now()));
+    }
+
+    /// The queue moved on by itself, drops the entry that just started.
+    fn pop_queued(&mut self, id: SongId) {
+        if self.queue.front() == Some(&id) {
+            self.queue.pop_front();
+        }
     }
 
     pub fn set_by_pindex(&mut self, index: usize, page: usize) -> Result<(), u8> {
         let absolute = absolute_index(index, page, self.typical_page_size);
-        if absolute >= self.filtered_songs.len() {
+        let Some(&id) = self.get_ordered().get(absolute) else {
             return Err(1);
+        };
+        if self.blacklist.contains(&id) {
+            return Err(0);
         }
+        self.renew_current_status(id);
+        self.stophandler = false;
+        self.setnext = self.algorithm_setnext();
+        Ok(())
+    }
 
-        let original_index = self.get_ordered()[absolute];
-        if self.blacklist.contains(&original_index) {
+    /// Plays a song picked from somewhere else than the list, like the history.
+    pub fn set_by_id(&mut self, id: SongId) -> Result<(), u8> {
+        if self.song(id).is_none() {
+            return Err(1);
+        }
+        if self.blacklist.contains(&id) {
             return Err(0);
         }
-        self.renew_current_status(original_index);
+        self.renew_current_status(id);
         self.stophandler = false;
-        self.setnext = self.algorithm_setnext().unwrap_or(usize::MAX);
+        self.setnext = self.algorithm_setnext();
+        Ok(())
+    }
+
+    /// Plays a queue entry, it leaves the queue.
+    pub fn set_by_queue(&mut self, position: usize) -> Result<(), u8> {
+        let id = self.queue.remove(position).ok_or(1)?;
+        self.renew_current_status(id);
+        self.stophandler = false;
+        self.setnext = self.algorithm_setnext();
         Ok(())
     }
 
@@ -310,78 +793,491 @@ impl Songs {
         if self.stophandler {
             return Duration::from_secs(0);
         }
-        self.all_songs
-            .get(self.current_index)
+        self.current_song()
             .map(|s| s.duration)
             .unwrap_or(Duration::from_secs(0))
     }
 
-    fn algorithm_setnext(&mut self) -> Result<usize, ()> {
-        if self.filtered_songs.is_empty() || self.stophandler {
-            return Err(());
+    fn algorithm_setnext(&mut self) -> Option<SongId> {
+        if self.stophandler {
+            return None;
         }
-        if self.setnext != usize::MAX && self.all_songs[self.setnext].forced {
-            return Ok(self.setnext);
+        if let Some(&next) = self.queue.front() {
+            return Some(next);
+        }
+        if self.filtered_songs.is_empty() {
+            return None;
         }
         if self.filtered_songs.len() == 1 {
-            let original_index = self.get_unordered()[0];
-            if self.blacklist.contains(&original_index) {
-                return Err(());
+            let id = self.get_unordered()[0];
+            if self.blacklist.contains(&id)
+                || (self.repeat == RepeatMode::Off && Some(id) == self.current)
+            {
+                return None;
             } else {
-                return Ok(original_index);
+                return Some(id);
             }
         }
-        // sequential
-        if let Ok(start) = self.get_filtered_index(self.current_index) {
+        let current = self.current;
+        match self.shuffle {
+            ShuffleMode::Off => {}
+            ShuffleMode::Bag | ShuffleMode::Album => return self.round_next(),
+            ShuffleMode::Weighted => return self.weighted_next(),
+        }
+        if let Some(start) = self.filtered_songs.iter().position(|&i| Some(i) == current) {
             for &i in &self.get_unordered()[start + 1..] {
                 if !self.blacklist.contains(&i) {
-                    return Ok(i);
+                    return Some(i);
                 }
             }
-            self.urandom();
+            if self.repeat == RepeatMode::Off {
+                return None;
+            }
             for &i in self.get_unordered().iter().take(start) {
                 if !self.blacklist.contains(&i) {
-                    return Ok(i);
+                    return Some(i);
                 }
             }
         } else {
-            self.urandom();
-            // this shit should NEVER run but is there just in case
+            // the current song isn't in the search result
             for &i in &self.filtered_songs {
-                if !self.blacklist.contains(&i) && i != self.current_index {
-                    return Ok(i);
+                if !self.blacklist.contains(&i) && Some(i) != current {
+                    return Some(i);
                 }
             }
         }
 
-        Err(())
+        None
     }
 
-    pub fn set_by_next(&mut self) -> Result<usize, ()> {
-        if self.setnext == usize::MAX {
-            Err(())
-        } else {
-            self.renew_current_status(self.setnext);
-            self.setnext = self.algorithm_setnext().unwrap_or(usize::MAX);
-            Ok(self.current_index)
+    /// The first song after the current one in play order that didn't play this round.
+    /// When everything played, the songs are shuffled again for the next round.
+    fn round_next(&mut self) -> Option<SongId> {
+        let current = self.current;
+        let can_play = |id: &SongId| Some(*id) != current && !self.blacklist.contains(id);
+        let len = self.filtered_songs.len();
+        let start = self
+            .filtered_songs
+            .iter()
+            .position(|&i| Some(i) == current)
+            .map_or(0, |p| p + 1);
+        let next = (0..len)
+            .map(|k| self.filtered_songs[(start + k) % len])
+            .find(|id| can_play(id) && !self.round.contains(id));
+        if next.is_some() || self.repeat == RepeatMode::Off {
+            return next;
         }
+        self.round.clear();
+        self.reorder();
+        self.filtered_songs
+            .iter()
+            .copied()
+            .find(|id| Some(*id) != current && !self.blacklist.contains(id))
+    }
+
+    /// Keeps the song it already picked while that one can still play next,
+    /// so the pick doesn't change every time something else does.
+    /// Without repeat every song plays once at most.
+    fn weighted_next(&mut self) -> Option<SongId> {
+        let can_play = |id: &SongId| {
+            Some(*id) != self.current
+                && !self.blacklist.contains(id)
+                && (self.repeat != RepeatMode::Off || !self.round.contains(id))
+        };
+        if let Some(next) = self.setnext
+            && can_play(&next)
+            && self.filtered_songs.contains(&next)
+        {
+            return Some(next);
+        }
+        let plays = |id: SongId| {
+            self.song(id)
+                .and_then(|s| self.plays.get(&s.path))
+                .copied()
+                .unwrap_or(0)
+        };
+        let candidates: Vec<(SongId, u32)> = self
+            .filtered_songs
+            .iter()
+            .filter(|id| can_play(id))
+            .map(|&id| (id, plays(id) + 1))
+            .collect();
+        candidates
+            .choose_weighted(&mut self.rng, |&(_, weight)| weight)
+            .ok()
+            .map(|&(id, _)| id)
+    }
+
+    pub fn set_by_next(&mut self) -> Result<SongId, ()> {
+        let next = self.setnext.ok_or(())?;
+        self.pop_queued(next);
+        self.renew_current_status(next);
+        self.setnext = self.algorithm_setnext();
+        Ok(next)
     }
-    pub fn prev(&mut self) -> Result<usize, ()> {
+    /// Used when the audio thread moved on by itself (gapless), path is what it's playing now.
+    pub fn set_by_path(&mut self, path: &str) -> Result<SongId, ()> {
         if self.stophandler {
-            Err(())
-        } else {
-            self.renew_current_status(self.current_index.saturating_sub(1));
-            self.setnext = self.algorithm_setnext().unwrap_or(usize::MAX);
-            Ok(self.current_index)
+            return Err(());
+        }
+        let id = self.all_songs.iter().find(|s| s.path == path).ok_or(())?.id;
+        self.pop_queued(id);
+        self.renew_current_status(id);
+        self.setnext = self.algorithm_setnext();
+        Ok(id)
+    }
+    /// Steps back through the history, the song that was playing leaves it.
+    /// Fails if nothing played before the current song.
+    pub fn prev(&mut self) -> Result<SongId, ()> {
+        if self.stophandler || self.history.len() < 2 {
+            return Err(());
         }
+        self.history.pop_back();
+        let (id, _) = *self.history.back().ok_or(())?;
+        self.current = Some(id);
+        self.setnext = self.algorithm_setnext();
+        Ok(id)
     }
 
     pub fn resume(&mut self) {
         self.stophandler = false;
-        self.setnext = self.algorithm_setnext().unwrap_or(usize::MAX);
+        self.setnext = self.algorithm_setnext();
     }
     pub fn stop(&mut self) {
         self.stophandler = true;
-        self.setnext = usize::MAX;
+        self.setnext = None;
+    }
+    /// Nothing is left to play with repeat off. Playing again starts a new round.
+    pub fn end(&mut self) {
+        self.stop();
+        self.round.clear();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::modules::library::Tags;
+
+    /// A scanned file with these tags, the directory it sits in is the artist the scan
+    /// guessed. For tests in any module that need songs in a library.
+    pub(crate) fn library_song(path: &str, tags: Tags) -> (LibraryFile, Probed) {
+        let artist = Path::new(path)
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .map(|dir| dir.to_string_lossy().to_string());
+        let file = LibraryFile {
+            path: path.to_string(),
+            artist,
+            album: None,
+        };
+        let probed = Probed {
+            tags,
+            ..Probed::default()
+        };
+        (file, probed)
+    }
+
+    fn batch(paths: &[&str]) -> Vec<(LibraryFile, Probed)> {
+        paths
+            .iter()
+            .map(|p| library_song(p, Tags::default()))
+            .collect()
+    }
+
+    fn path_of(songs: &Songs, id: Option<SongId>) -> &str {
+        &songs.song(id.unwrap()).unwrap().path
+    }
+
+    #[test]
+    fn tags_decide_the_order() {
+        let album = |title: &str, year, disc, track| Tags {
+            title: Some(title.into()),
+            album_artist: Some("Band".into()),
+            album: Some(format!("Album {}", year)),
+            year: Some(year),
+            disc: Some(disc),
+            track: Some(track),
+            ..Tags::default()
+        };
+        let mut songs = Songs::new();
+        songs.add(vec![
+            library_song("a/late.mp3", album("Late", 2001, 1, 1)),
+            library_song("z/second.mp3", album("Second", 1999, 2, 1)),
+            library_song("b/first.mp3", album("First", 1999, 1, 3)),
+            library_song("c/untagged.mp3", Tags::default()),
+        ]);
+        let order: Vec<&str> = songs
+            .get_ordered()
+            .into_iter()
+            .map(|id| songs.song(id).unwrap().name.as_str())
+            .collect();
+        assert_eq!(order, ["first", "second", "late", "untagged"]);
+
+        let first = songs.song(songs.get_ordered()[0]).unwrap();
+        assert_eq!(first.artist, "b");
+        assert_eq!(first.display_name(false), "first");
+        assert_eq!(first.display_name(true), "First");
+        assert!(first.searchable.contains("band"));
+    }
+
+    #[test]
+    fn ids_follow_their_songs() {
+        let mut songs = Songs::new();
+        songs.add(batch(&["b/2.mp3", "b/1.mp3"]));
+        songs.set_by_pindex(1, 1).unwrap(); // b/2
+        let playing = songs.current;
+        songs.blacklist(songs.get_ordered()[0]); // b/1
+        songs.add(batch(&["a/1.mp3", "c/1.mp3", "b/2.mp3"]));
+        assert_eq!(songs.all_songs.len(), 4);
+        assert_eq!(songs.current, playing);
+        assert_eq!(path_of(&songs, songs.current), "b/2.mp3");
+        assert_eq!(path_of(&songs, Some(songs.blacklist[0])), "b/1.mp3");
+        assert_eq!(path_of(&songs, songs.setnext), "c/1.mp3");
+
+        // the playing song is held back, the rest goes
+        assert!(songs.remove(vec!["b".into(), "c/1.mp3".into()]));
+        assert_eq!(songs.all_songs.len(), 2);
+        assert!(songs.blacklist.is_empty());
+        assert_eq!(path_of(&songs, songs.current), "b/2.mp3");
+        assert_eq!(path_of(&songs, songs.setnext), "a/1.mp3");
+        assert!(!songs.sweep());
+
+        songs.set_by_next().unwrap();
+        assert!(songs.sweep());
+        assert_eq!(songs.all_songs.len(), 1);
+        assert!(songs.song(playing.unwrap()).is_none());
+        assert_eq!(path_of(&songs, songs.current), "a/1.mp3");
+    }
+
+    #[test]
+    fn queue_goes_first() {
+        let mut songs = Songs::new();
+        songs.add(batch(&["a/1.mp3", "a/2.mp3", "a/3.mp3", "a/4.mp3"]));
+        let ids = songs.get_ordered();
+        songs.set_by_pindex(0, 1).unwrap();
+        assert_eq!(songs.setnext, Some(ids[1]));
+
+        songs.enqueue(ids[3]);
+        songs.enqueue(ids[2]);
+        assert_eq!(songs.setnext, Some(ids[3]));
+        assert!(songs.next_is_queued());
+        assert_eq!(songs.move_queued(1, true), Some(0));
+        assert_eq!(songs.setnext, Some(ids[2]));
+        songs.set_next(ids[3]);
+        assert_eq!(songs.queue, [ids[3], ids[2]]);
+
+        // playing takes songs off the front
+        assert_eq!(songs.set_by_next(), Ok(ids[3]));
+        assert_eq!(songs.set_by_next(), Ok(ids[2]));
+        assert!(songs.queue.is_empty());
+        assert!(!songs.next_is_queued());
+        assert_eq!(songs.setnext, Some(ids[3]));
+
+        // blacklisted songs can't be queued and leave the queue
+        songs.enqueue_all();
+        assert_eq!(songs.queue.len(), 4);
+        songs.blacklist(ids[0]);
+        assert_eq!(songs.queue, [ids[1], ids[2], ids[3]]);
+        songs.enqueue(ids[0]);
+        songs.unqueue(ids[2]);
+        songs.dequeue(0);
+        assert_eq!(songs.queue, [ids[3]]);
+        songs.clear_queue();
+        assert_eq!(songs.setnext, Some(ids[3]));
+    }
+
+    #[test]
+    fn previous_walks_the_history() {
+        let mut songs = Songs::new();
+        songs.add(batch(&["a/1.mp3", "a/2.mp3", "a/3.mp3"]));
+        let ids = songs.get_ordered();
+        songs.set_by_pindex(2, 1).unwrap();
+        assert_eq!(songs.prev(), Err(()));
+        songs.set_by_pindex(0, 1).unwrap();
+        songs.set_by_next().unwrap();
+        let played: Vec<SongId> = songs.history.iter().map(|&(id, _)| id).collect();
+        assert_eq!(played, [ids[2], ids[0], ids[1]]);
+
+        // not the alphabetical neighbour, what actually played
+        assert_eq!(songs.prev(), Ok(ids[0]));
+        assert_eq!(songs.prev(), Ok(ids[2]));
+        assert_eq!(songs.current, Some(ids[2]));
+        assert_eq!(songs.prev(), Err(()));
+        assert_eq!(songs.history.len(), 1);
+
+        for _ in 0..HISTORY_LEN + 5 {
+            songs.set_by_next().unwrap();
+        }
+        assert_eq!(songs.history.len(), HISTORY_LEN);
+    }
+
+    fn album_batch(paths: &[&str]) -> Vec<(LibraryFile, Probed)> {
+        let mut batch = batch(paths);
+        for (file, _) in &mut batch {
+            file.album = Path::new(&file.path)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string());
+        }
+        batch
+    }
+
+    /// Plays `count` songs from the start of the list on, returns what played.
+    fn play_through(songs: &mut Songs, count: usize) -> Vec<SongId> {
+        songs.set_by_pindex(0, 1).unwrap();
+        let mut played = vec![songs.current.unwrap()];
+        for _ in 1..count {
+            played.push(songs.set_by_next().unwrap());
+        }
+        played
+    }
+
+    #[test]
+    fn bag_plays_everything_once_per_round() {
+        let paths: Vec<String> = (0..20).map(|i| format!("a/{:02}.mp3", i)).collect();
+        let paths: Vec<&str> = paths.iter().map(|p| p.as_str()).collect();
+        let mut songs = Songs::new();
+        songs.seed(7);
+        songs.add(batch(&paths));
+        songs.set_shuffle(ShuffleMode::Bag);
+        let played = play_through(&mut songs, 60);
+        for round in played.chunks(20) {
+            let mut round = round.to_vec();
+            round.sort();
+            round.dedup();
+            assert_eq!(round.len(), 20);
+        }
+        assert!(played.windows(2).all(|w| w[0] != w[1]));
+        assert_ne!(played[..20], played[20..40]);
+
+        // same seed, same order
+        let mut again = Songs::new();
+        again.seed(7);
+        again.add(batch(&paths));
+        again.set_shuffle(ShuffleMode::Bag);
+        assert_eq!(play_through(&mut again, 60), played);
+    }
+
+    #[test]
+    fn album_shuffle_keeps_albums_together() {
+        let mut songs = Songs::new();
+        songs.seed(3);
+        songs.add(album_batch(&[
+            "a/x/1.mp3", "a/x/2.mp3", "a/x/3.mp3", "b/y/1.mp3", "b/y/2.mp3", "c/z/1.mp3",
+        ]));
+        songs.set_shuffle(ShuffleMode::Album);
+        let played = play_through(&mut songs, 6);
+        let albums: Vec<&str> = played
+            .iter()
+            .map(|&id| songs.song(id).unwrap().album.as_str())
+            .collect();
+        let mut runs = albums.clone();
+        runs.dedup();
+        assert_eq!(runs.len(), 3);
+        for id in played.windows(2) {
+            let (a, b) = (songs.song(id[0]).unwrap(), songs.song(id[1]).unwrap());
+            if a.album == b.album {
+                assert!(a.name < b.name);
+            }
+        }
+
+        // two "Live" albums by different bands are two blocks, not one
+        let mut live = batch(&["d/1.mp3", "d/2.mp3", "e/1.mp3", "e/2.mp3", "f/1.mp3"]);
+        for (file, _) in &mut live {
+            file.album = Some("Live".into());
+        }
+        let mut songs = Songs::new();
+        songs.seed(2);
+        songs.add(live);
+        songs.set_shuffle(ShuffleMode::Album);
+        let artists: Vec<&str> = songs
+            .get_unordered()
+            .iter()
+            .map(|&id| songs.song(id).unwrap().artist.as_str())
+            .collect();
+        let mut runs = artists.clone();
+        runs.dedup();
+        assert_eq!(runs.len(), 3);
+        assert!(artists.windows(2).all(|w| w != ["d", "e"]));
+    }
+
+    #[test]
+    fn weighted_prefers_played_songs() {
+        let mut songs = Songs::new();
+        songs.seed(11);
+        songs.add(batch(&["a/1.mp3", "a/2.mp3", "a/3.mp3", "a/4.mp3"]));
+        songs.plays.insert("a/4.mp3".into(), 50);
+        songs.set_shuffle(ShuffleMode::Weighted);
+        let ids = songs.get_ordered();
+        songs.blacklist(ids[1]);
+        let played = play_through(&mut songs, 40);
+        assert!(played.windows(2).all(|w| w[0] != w[1]));
+        assert!(!played.contains(&ids[1]));
+        let count = |id| played.iter().filter(|&&p| p == id).count();
+        // it can't come twice in a row, so every other song at most
+        assert!(count(ids[3]) >= 15);
+        assert!(count(ids[3]) > count(ids[0]) && count(ids[3]) > count(ids[2]));
+    }
+
+    #[test]
+    fn repeat_off_stops_at_the_end() {
+        let mut songs = Songs::new();
+        songs.add(batch(&["a/1.mp3", "a/2.mp3"]));
+        songs.set_repeat(RepeatMode::Off);
+        play_through(&mut songs, 2);
+        assert!(songs.set_by_next().is_err());
+        songs.set_repeat(RepeatMode::All);
+        let next = songs.set_by_next().ok();
+        assert_eq!(path_of(&songs, next), "a/1.mp3");
+
+        songs.set_shuffle(ShuffleMode::Bag);
+        songs.set_repeat(RepeatMode::Off);
+        play_through(&mut songs, 2);
+        assert!(songs.set_by_next().is_err());
+        songs.end();
+        assert_eq!(play_through(&mut songs, 2).len(), 2);
+    }
+
+    #[test]
+    fn playlist_is_the_list() {
+        let mut songs = Songs::new();
+        songs.add(batch(&["a/1.mp3", "b/1.mp3", "c/1.mp3"]));
+        songs.set_source(Some(Playlist {
+            name: "mix".into(),
+            paths: vec!["c/1.mp3".into(), "gone.mp3".into(), "a/1.mp3".into()],
+            file: PathBuf::from("mix.m3u8"),
+        }));
+        let ordered: Vec<&str> = songs
+            .get_ordered()
+            .into_iter()
+            .map(|id| path_of(&songs, Some(id)))
+            .collect();
+        assert_eq!(ordered, ["c/1.mp3", "a/1.mp3"]);
+        songs.set_by_pindex(0, 1).unwrap();
+        let next = songs.set_by_next().ok();
+        assert_eq!(path_of(&songs, next), "a/1.mp3");
+
+        songs.set_source(None);
+        assert_eq!(songs.get_ordered().len(), 3);
+    }
+
+    #[test]
+    fn search_ranks_better_matches_first() {
+        let mut songs = Songs::new();
+        songs.add(batch(&[
+            "a/Ohalo.mp3",
+            "b/Halo.mp3",
+            "c/h a l o.mp3",
+            "d/Hello.mp3",
+        ]));
+        songs.search("halo").unwrap();
+        let ordered: Vec<&str> = songs
+            .get_ordered()
+            .into_iter()
+            .map(|id| path_of(&songs, Some(id)))
+            .collect();
+        assert_eq!(ordered, ["b/Halo.mp3", "c/h a l o.mp3", "a/Ohalo.mp3"]);
+        assert!(songs.search("artist:(").is_err());
+        assert_eq!(songs.get_ordered().len(), 3);
     }
 }
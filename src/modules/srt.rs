@@ -0,0 +1,259 @@
+// SubRip (.srt) and WebVTT (.vtt) cues turned into the same events ASS subtitles load into.
+//
+//   1                                  WEBVTT
+//   00:00:01,000 --> 00:00:04,000
+//   First line                         intro
+//   <i>second</i> line                 00:01.000 --> 00:04.000 align:start line:0
+//                                      <v Singer>First line
+//
+// Lines of a cue are joined with \N and tags like <i> or <font> are dropped, the
+// subtitle line has no styles to give them. VTT cue settings only place the cue, as \an.
+// A cue that can't be read is reported and skipped, the rest still loads.
+
+use crate::libkagami::complex::overrides::ASSOverride;
+use crate::libkagami::core::SubstationAlpha;
+use crate::libkagami::tags::ASSText;
+use crate::modules::lrc::{empty_script, event};
+
+/// "hh:mm:ss,mmm", "hh:mm:ss.mmm" or "mm:ss.mmm" in milliseconds.
+fn parse_timestamp(text: &str) -> Option<i64> {
+    let (clock, ms) = text.trim().rsplit_once([',', '.'])?;
+    if ms.len() != 3 || !ms.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let parts: Vec<&str> = clock.split(':').collect();
+    if !(2..=3).contains(&parts.len()) {
+        return None;
+    }
+    let mut total = 0i64;
+    for (n, part) in parts.iter().enumerate() {
+        let value: i64 = part.parse().ok().filter(|v| *v >= 0)?;
+        // hours can be any length, minutes and seconds are two digits under 60
+        if (n > 0 || parts.len() == 2) && (part.len() != 2 || value >= 60) {
+            return None;
+        }
+        total = total.checked_mul(60)?.checked_add(value)?;
+    }
+    total.checked_mul(1000)?.checked_add(ms.parse().ok()?)
+}
+
+/// "start --> end" and whatever follows it, the cue settings in VTT.
+fn parse_timing(line: &str) -> Option<(i64, i64, &str)> {
+    let (start, rest) = line.split_once("-->")?;
+    let rest = rest.trim_start();
+    let (end, settings) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let (start, end) = (parse_timestamp(start)?, parse_timestamp(end)?);
+    (end >= start).then_some((start, end, settings.trim()))
+}
+
+/// \an for VTT cue settings: line: says top or bottom, align: says left, center or right.
+fn alignment(settings: &str) -> Option<u8> {
+    let mut row = 0;
+    let mut column = 2;
+    for setting in settings.split_whitespace() {
+        match setting.split_once(':') {
+            Some(("line", value)) => {
+                let value = value.split(',').next().unwrap_or("");
+                let top = match value.strip_suffix('%') {
+                    Some(percent) => percent.parse::<f32>().is_ok_and(|p| p < 50.0),
+                    // line numbers count from the top when positive, from the bottom otherwise
+                    None => value.parse::<i32>().is_ok_and(|n| n >= 0),
+                };
+                row = if top { 6 } else { 0 };
+            }
+            Some(("align", "start" | "left")) => column = 1,
+            Some(("align", "end" | "right")) => column = 3,
+            Some(("align", _)) => column = 2,
+            _ => {}
+        }
+    }
+    (row != 0 || column != 2).then_some(row + column)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&lrm;", "")
+        .replace("&rlm;", "")
+        .replace("&amp;", "&")
+}
+
+/// Cue text without its tags, placed by the cue settings.
+fn cue_text(lines: &[&str], settings: &str) -> Vec<ASSText> {
+    let mut data = Vec::new();
+    if let Some(an) = alignment(settings) {
+        data.push(ASSText::Override(ASSOverride::An(an)));
+    }
+    let mut raw = String::new();
+    let text = lines.join("\\N");
+    let mut rest = text.as_str();
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        raw += &rest[..open];
+        rest = &rest[open + close + 1..];
+    }
+    raw += rest;
+    if !raw.is_empty() {
+        data.push(ASSText::RawText(unescape(&raw)));
+    }
+    data
+}
+
+/// Blocks of lines between blank lines, with the number of their first line.
+fn blocks(buf: &str) -> Vec<(usize, Vec<&str>)> {
+    let mut blocks = Vec::new();
+    let mut block: Option<(usize, Vec<&str>)> = None;
+    for (n, line) in buf.trim_start_matches('\u{feff}').lines().enumerate() {
+        if line.trim().is_empty() {
+            blocks.extend(block.take());
+        } else {
+            block.get_or_insert((n + 1, Vec::new())).1.push(line);
+        }
+    }
+    blocks.extend(block);
+    blocks
+}
+
+fn parse_cues<'a>(
+    blocks: impl Iterator<Item = (usize, Vec<&'a str>)>,
+    problems: &mut Vec<String>,
+) -> SubstationAlpha {
+    let mut events = Vec::new();
+    for (first, lines) in blocks {
+        // a number in SRT, an identifier in VTT, either can be missing
+        let Some(timing) = lines.iter().position(|l| l.contains("-->")) else {
+            problems.push(format!("{}: a cue without a `start --> end` line", first));
+            continue;
+        };
+        match parse_timing(lines[timing]) {
+            Some((start, end, settings)) => {
+                let text = cue_text(&lines[timing + 1..], settings);
+                events.push(event(start, end, 0, text));
+            }
+            None => problems.push(format!(
+                "{}: can't read the times in `{}`",
+                first + timing,
+                lines[timing].trim()
+            )),
+        }
+    }
+    events.sort_by_key(|e| {
+        let t = &e.start;
+        (t.hours, t.minutes, t.seconds, t.centiseconds)
+    });
+    SubstationAlpha {
+        script_info: empty_script(String::new()),
+        v4p_styles: vec![],
+        events,
+    }
+}
+
+/// The cues of an SRT file and what was wrong with the ones that were skipped, as
+/// "line: problem".
+pub fn parse_srt(buf: &str) -> (SubstationAlpha, Vec<String>) {
+    let mut problems = Vec::new();
+    let subs = parse_cues(blocks(buf).into_iter(), &mut problems);
+    (subs, problems)
+}
+
+/// The cues of a WebVTT file, NOTE, STYLE and REGION blocks are skipped.
+pub fn parse_vtt(buf: &str) -> (SubstationAlpha, Vec<String>) {
+    let mut problems = Vec::new();
+    let mut blocks = blocks(buf).into_iter().peekable();
+    match blocks.peek() {
+        Some((_, header))
+            if header[0]
+                .trim_start_matches('\u{feff}')
+                .starts_with("WEBVTT") =>
+        {
+            blocks.next();
+        }
+        _ => problems.push("1: doesn't start with WEBVTT".to_string()),
+    }
+    let cues = blocks.filter(|(_, lines)| {
+        let first = lines[0].trim();
+        !["NOTE", "STYLE", "REGION"]
+            .iter()
+            .any(|kind| first == *kind || first.starts_with(&format!("{} ", kind)))
+    });
+    let subs = parse_cues(cues, &mut problems);
+    (subs, problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libkagami::core::Event;
+
+    fn shown(e: &Event) -> (u32, u32, String) {
+        let ms = |t: &crate::libkagami::complex::types::AssTime| {
+            ((t.hours as u32 * 60 + t.minutes as u32) * 60 + t.seconds as u32) * 1000
+                + t.centiseconds as u32 * 10
+        };
+        let text = e
+            .text
+            .data
+            .iter()
+            .map(|t| match t {
+                ASSText::RawText(s) => s.clone(),
+                ASSText::Override(ASSOverride::An(an)) => format!("{{an{}}}", an),
+                ASSText::Override(_) => "{?}".to_string(),
+            })
+            .collect();
+        (ms(&e.start), ms(&e.end), text)
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("01:02:03,456"), Some(3_723_456));
+        assert_eq!(parse_timestamp("100:00:00.000"), Some(360_000_000));
+        assert_eq!(parse_timestamp("02:03.456"), Some(123_456));
+        assert_eq!(parse_timestamp("00:61:00,000"), None);
+        assert_eq!(parse_timestamp("00:00:01,5"), None);
+        assert_eq!(parse_timestamp("1:2:3,000"), None);
+        assert_eq!(parse_timestamp("garbage"), None);
+        assert_eq!(parse_timestamp("9999999999999999:00:00,000"), None);
+    }
+
+    #[test]
+    fn srt_cues_survive_bad_ones() {
+        let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\nHello <i>there</i>\r\nsecond &amp; line\r\n\r\n\
+                   2\r\n00:00:0x,000 --> 00:00:04,000\r\nbroken\r\n\r\n\
+                   3\r\n00:00:05,000 --> 00:00:06,000 X1:10 X2:20\r\n<font color=\"red\">red</font>\r\n\r\n\
+                   4\r\njust text\r\n";
+        let (subs, problems) = parse_srt(srt);
+        let events: Vec<_> = subs.events.iter().map(shown).collect();
+        assert_eq!(
+            events,
+            [
+                (1000, 2500, "Hello there\\Nsecond & line".to_string()),
+                (5000, 6000, "red".to_string()),
+            ]
+        );
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("7: can't read the times"));
+        assert!(problems[1].starts_with("14: a cue without"));
+    }
+
+    #[test]
+    fn vtt_settings_and_blocks() {
+        let vtt = "WEBVTT - lyrics\n\nNOTE a comment\nover two lines\n\nSTYLE\n::cue { color: red }\n\n\
+                   intro\n00:01.000 --> 00:02.000 align:start line:0\n<v Singer>Top left</v>\n\n\
+                   00:00:03.000 --> 00:00:04.000 line:-1\n<c.loud>Bottom</c>\n";
+        let (subs, problems) = parse_vtt(vtt);
+        assert!(problems.is_empty());
+        let events: Vec<_> = subs.events.iter().map(shown).collect();
+        assert_eq!(
+            events,
+            [
+                (1000, 2000, "{an7}Top left".to_string()),
+                (3000, 4000, "Bottom".to_string()),
+            ]
+        );
+        let (_, problems) = parse_vtt("00:01.000 --> 00:02.000\nno header\n");
+        assert_eq!(problems, ["1: doesn't start with WEBVTT"]);
+    }
+}
@@ -1,6 +1,58 @@
-use std::{path::PathBuf, time::Duration};
-use crate::libkagami::core::SubstationAlpha;
+use crate::libkagami::complex::overrides::ASSOverride;
+use crate::libkagami::core::{Event, SubstationAlpha};
 use crate::libkagami::tags::ASSText;
+use crate::modules::lrc::parse_lrc;
+use crate::modules::srt::{parse_srt, parse_vtt};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use unicode_width::UnicodeWidthChar;
+
+/// Sidecar formats that can be read, in the default lookup order.
+pub const FORMATS: &[&str] = &["ass", "lrc", "srt", "vtt"];
+
+/// A line of subtitle text at some time.
+pub struct Shown {
+    pub text: String,
+    /// Byte ranges of text the karaoke has already gone over.
+    pub sung: Vec<Range<usize>>,
+    /// Where the line goes, like the \an column: 1 left, 2 centered, 3 right.
+    pub column: u8,
+}
+
+/// A character of event text and whether it has been sung.
+type Cell = (char, bool);
+
+/// A karaoke syllable: when it starts and ends in seconds, where its text starts and
+/// whether it sweeps (\kf, \K) or turns all at once (\k, \ko).
+struct Syllable {
+    start: f64,
+    end: f64,
+    from: usize,
+    sweep: bool,
+}
+
+impl Syllable {
+    /// The part of the syllable's text, from..text.len(), that is sung at time.
+    fn sung(&self, text: &str, time: f64) -> Option<Range<usize>> {
+        if time < self.start || self.from == text.len() {
+            return None;
+        }
+        let syllable = &text[self.from..];
+        if !self.sweep || time >= self.end {
+            return Some(self.from..text.len());
+        }
+        let chars = syllable.chars().count();
+        let done = ((time - self.start) / (self.end - self.start) * chars as f64) as usize;
+        let len = syllable
+            .char_indices()
+            .nth(done)
+            .map_or(syllable.len(), |(i, _)| i);
+        (len > 0).then(|| self.from..self.from + len)
+    }
+}
 
 pub struct PreciseSubtitleImport {
     subtitles: SubstationAlpha
@@ -24,6 +76,47 @@ impl PreciseSubtitleImport {
                 },
             }
         }
+    /// Events read some other way, like lyrics from the song's tags.
+    pub fn from_subtitles(subtitles: SubstationAlpha) -> Self {
+        Self { subtitles }
+    }
+    /// Reads a sidecar file, the extension says which format it is. Parts of it that
+    /// couldn't be read are skipped and returned as problems.
+    pub fn load(path: &Path) -> Result<(Self, Vec<String>), String> {
+        let ext = path
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_ascii_lowercase();
+        let mut sub = Self::new();
+        let mut problems = Vec::new();
+        match ext.as_str() {
+            "ass" => sub.asyncgate(&path.to_string_lossy()),
+            "lrc" | "srt" | "vtt" => {
+                // lyric sites hand out all sorts of encodings, a bad byte only costs a character
+                let bytes =
+                    std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                let buf = String::from_utf8_lossy(&bytes);
+                let (subtitles, found) = match ext.as_str() {
+                    "lrc" => (parse_lrc(&buf), Vec::new()),
+                    "srt" => parse_srt(&buf),
+                    _ => parse_vtt(&buf),
+                };
+                sub.subtitles = subtitles;
+                problems = found
+                    .into_iter()
+                    .map(|p| format!("{}:{}", path.display(), p))
+                    .collect();
+            }
+            _ => {
+                return Err(format!(
+                    "{}: not a subtitle format neocrystal reads",
+                    path.display()
+                ));
+            }
+        }
+        Ok((sub, problems))
+    }
     pub fn asyncgate(&mut self, path: &str) {
         tokio::runtime::Runtime::new()
             .unwrap()
@@ -31,25 +124,232 @@ impl PreciseSubtitleImport {
     }
 
     pub async fn load_subtitles(&mut self, path: &str) {
-        self.subtitles = SubstationAlpha::load(PathBuf::from(path), false).await;
+        self.subtitles = SubstationAlpha::load(PathBuf::from(path), true).await;
     }
-    pub fn get_from_time(&self, time: Duration) -> Option<String> {
+    /// The events showing at time as lines of at most width columns. Top aligned events
+    /// come first, then middle, then bottom ones, lower layers before higher ones.
+    pub fn lines_at(&self, time: Duration, width: usize) -> Vec<Shown> {
         let time_secs = time.as_secs_f64();
+        let mut events: Vec<(u8, &Event)> = self
+            .subtitles
+            .events
+            .iter()
+            .filter(|e| {
+                let start = ass_time_to_secs(&e.start);
+                let end   = ass_time_to_secs(&e.end);
+                time_secs >= start && time_secs < end
+            })
+            .map(|e| (self.alignment(e), e))
+            .collect();
+        // \an is a numpad position, 7 8 9 are the top row
+        events.sort_by_key(|&(an, e)| (2 - (an - 1) / 3, e.layer));
 
-        let event = self.subtitles.events.iter().find(|e| {
-            let start = ass_time_to_secs(&e.start);
-            let end   = ass_time_to_secs(&e.end);
-            time_secs >= start && time_secs < end
-        })?;
+        let wrap_style = self.subtitles.script_info.wrap_style;
+        let mut lines = Vec::new();
+        for (an, event) in events {
+            let (text, sung) = karaoke(event, time_secs);
+            if text.trim().is_empty() {
+                continue;
+            }
+            let cells = cells(&text, &sung, wrap_style);
+            for line in wrap(&cells, width, wrap_style) {
+                lines.push(shown(&line, (an - 1) % 3 + 1));
+            }
+        }
+        lines
+    }
 
-        let text: String = event.text.data.iter()
-            .filter_map(|node| match node {
-                ASSText::RawText(s) => Some(s.as_str()),
-                ASSText::Override(_) => None,
+    /// \an of the event, from its text or else from its style.
+    fn alignment(&self, event: &Event) -> u8 {
+        event
+            .text
+            .data
+            .iter()
+            .find_map(|node| match node {
+                ASSText::Override(ASSOverride::An(an)) => Some(*an),
+                _ => None,
             })
-            .collect();
+            .or_else(|| {
+                self.subtitles
+                    .v4p_styles
+                    .iter()
+                    .find(|style| style.name == event.style)
+                    .map(|style| style.alignment)
+            })
+            .filter(|an| (1..=9).contains(an))
+            .unwrap_or(2)
+    }
+}
+
+/// The event's text and what of it is sung at time. Karaoke syllables count from the
+/// event's start, each one starting where the ones before it ended.
+fn karaoke(event: &Event, time_secs: f64) -> (String, Vec<Range<usize>>) {
+    let mut text = String::new();
+    let mut sung: Vec<Range<usize>> = Vec::new();
+    let mut syllable: Option<Syllable> = None;
+    let mut at = ass_time_to_secs(&event.start);
+    for node in &event.text.data {
+        let (cs, sweep) = match node {
+            ASSText::RawText(s) => {
+                text.push_str(s);
+                continue;
+            }
+            ASSText::Override(ASSOverride::K(cs) | ASSOverride::Ko(cs)) => (*cs, false),
+            ASSText::Override(ASSOverride::Kf(cs) | ASSOverride::KSweep(cs)) => (*cs, true),
+            ASSText::Override(_) => continue,
+        };
+        add_range(
+            &mut sung,
+            syllable.take().and_then(|s| s.sung(&text, time_secs)),
+        );
+        let end = at + cs as f64 / 100.0;
+        syllable = Some(Syllable {
+            start: at,
+            end,
+            from: text.len(),
+            sweep,
+        });
+        at = end;
+    }
+    add_range(&mut sung, syllable.and_then(|s| s.sung(&text, time_secs)));
+    (text, sung)
+}
+
+/// Plain text, like lyrics from the tags, wrapped as much on a line as fits.
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let cells: Vec<Cell> = text.chars().map(|c| (c, false)).collect();
+    wrap(&cells, width, 1)
+        .iter()
+        .map(|line| line.iter().map(|&(c, _)| c).collect())
+        .collect()
+}
+
+/// Text as cells with the escapes done: \N is a line break, \n too with wrap style 2
+/// and a space otherwise, \h is a space lines don't break at.
+fn cells(text: &str, sung: &[Range<usize>], wrap_style: u8) -> Vec<Cell> {
+    let mut cells = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let escaped = match (c, chars.peek().map(|&(_, next)| next)) {
+            ('\\', Some('N')) => Some('\n'),
+            ('\\', Some('n')) if wrap_style == 2 => Some('\n'),
+            ('\\', Some('n')) => Some(' '),
+            ('\\', Some('h')) => Some('\u{a0}'),
+            _ => None,
+        };
+        if escaped.is_some() {
+            chars.next();
+        }
+        cells.push((escaped.unwrap_or(c), sung.iter().any(|r| r.contains(&i))));
+    }
+    cells
+}
+
+fn width_of(cells: &[Cell]) -> usize {
+    cells.iter().map(|(c, _)| c.width().unwrap_or(0)).sum()
+}
+
+/// Greedy line breaks for words this wide, as ranges of words.
+fn breaks(widths: &[usize], width: usize) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let (mut start, mut used) = (0, 0);
+    for (i, &w) in widths.iter().enumerate() {
+        if i > start && used + 1 + w > width {
+            lines.push(start..i);
+            start = i;
+        }
+        used = if i == start { w } else { used + 1 + w };
+    }
+    lines.push(start..widths.len());
+    lines
+}
 
-        if text.is_empty() { None } else { Some(text) }
+/// Line breaks the way the wrap style says: 0 as even as they go with the top line
+/// wider, 1 as much on a line as fits, 2 none, 3 like 0 with the bottom line wider.
+fn balanced(widths: &[usize], width: usize, wrap_style: u8) -> Vec<Range<usize>> {
+    let n = widths.len();
+    let fill = |target: usize| -> Vec<Range<usize>> {
+        if wrap_style != 3 {
+            return breaks(widths, target);
+        }
+        // filled from the end, what's left over goes on top
+        let reversed: Vec<usize> = widths.iter().rev().copied().collect();
+        breaks(&reversed, target)
+            .into_iter()
+            .rev()
+            .map(|r| n - r.end..n - r.start)
+            .collect()
+    };
+    let greedy = fill(width);
+    if !matches!(wrap_style, 0 | 3) || greedy.len() < 2 {
+        return greedy;
+    }
+    // the narrowest lines that don't make more of them
+    let total = widths.iter().sum::<usize>() + n - 1;
+    (total.div_ceil(greedy.len())..width)
+        .map(fill)
+        .find(|lines| lines.len() <= greedy.len())
+        .unwrap_or(greedy)
+}
+
+/// Cells in lines of at most width columns. Words longer than that are cut.
+fn wrap(cells: &[Cell], width: usize, wrap_style: u8) -> Vec<Vec<Cell>> {
+    let width = if wrap_style == 2 {
+        usize::MAX
+    } else {
+        width.max(1)
+    };
+    let mut lines = Vec::new();
+    for paragraph in cells.split(|&(c, _)| c == '\n') {
+        let mut words: Vec<&[Cell]> = Vec::new();
+        for word in paragraph
+            .split(|&(c, _)| c == ' ')
+            .filter(|w| !w.is_empty())
+        {
+            let mut start = 0;
+            for end in 1..word.len() {
+                if width_of(&word[start..=end]) > width {
+                    words.push(&word[start..end]);
+                    start = end;
+                }
+            }
+            words.push(&word[start..]);
+        }
+        let widths: Vec<usize> = words.iter().map(|w| width_of(w)).collect();
+        for range in balanced(&widths, width, wrap_style) {
+            let mut line: Vec<Cell> = Vec::new();
+            for word in &words[range] {
+                // a space is sung once the word before it is
+                if let Some(&(_, sung)) = line.last() {
+                    line.push((' ', sung));
+                }
+                line.extend_from_slice(word);
+            }
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+fn shown(cells: &[Cell], column: u8) -> Shown {
+    let mut text = String::new();
+    let mut sung = Vec::new();
+    for &(c, is_sung) in cells {
+        let start = text.len();
+        text.push(c);
+        if is_sung {
+            add_range(&mut sung, Some(start..text.len()));
+        }
+    }
+    Shown { text, sung, column }
+}
+
+/// Syllables sung one after another make one range.
+fn add_range(ranges: &mut Vec<Range<usize>>, range: Option<Range<usize>>) {
+    let Some(range) = range else { return };
+    match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
     }
 }
 
@@ -59,3 +359,161 @@ fn ass_time_to_secs(t: &crate::libkagami::complex::types::AssTime) -> f64 {
     + t.seconds as f64
     + t.centiseconds as f64 / 100.0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::lrc::event;
+
+    fn import(wrap_style: u8, events: Vec<Event>) -> PreciseSubtitleImport {
+        let mut sub = PreciseSubtitleImport::new();
+        sub.subtitles.script_info.wrap_style = wrap_style;
+        sub.subtitles.events = events;
+        sub
+    }
+
+    fn text(s: &str) -> Vec<ASSText> {
+        vec![ASSText::RawText(s.to_string())]
+    }
+
+    fn lines(sub: &PreciseSubtitleImport, width: usize) -> Vec<String> {
+        sub.lines_at(Duration::from_secs(2), width)
+            .into_iter()
+            .map(|l| l.text)
+            .collect()
+    }
+
+    fn sung_at(data: Vec<ASSText>, ms: u64) -> Vec<String> {
+        let sub = import(0, vec![event(1000, 5000, 0, data)]);
+        let shown = sub.lines_at(Duration::from_millis(ms), 100).remove(0);
+        shown
+            .sung
+            .iter()
+            .map(|r| shown.text[r.clone()].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn karaoke_counts_from_the_event_start() {
+        let k = |o: ASSOverride, t: &str| [ASSText::Override(o), ASSText::RawText(t.to_string())];
+        let line = || {
+            [
+                k(ASSOverride::K(50), "One "),
+                k(ASSOverride::Kf(100), "twoo"),
+                k(ASSOverride::Ko(50), "!"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(sung_at(line(), 1000), ["One "]);
+        // half way through the sweep
+        assert_eq!(sung_at(line(), 2000), ["One tw"]);
+        assert_eq!(sung_at(line(), 2500), ["One twoo!"]);
+        // text before the first syllable isn't karaoke
+        let mut intro = vec![ASSText::RawText("- ".to_string())];
+        intro.extend(line());
+        assert_eq!(sung_at(intro, 1200), ["One "]);
+    }
+
+    /// An .ass sidecar holding these events, written to a temporary file and loaded.
+    fn load_ass(name: &str, events: &str) -> PreciseSubtitleImport {
+        let file = format!("neocrystal-{}-{}.ass", name, std::process::id());
+        let path = std::env::temp_dir().join(file);
+        let ass = format!(
+            "[Script Info]\nScriptType: v4.00+\n\n[V4+ Styles]\n\
+             Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+             Style: Default,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\n\n\
+             [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n{}",
+            events
+        );
+        std::fs::write(&path, ass).unwrap();
+        let (sub, problems) = PreciseSubtitleImport::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(problems.is_empty());
+        sub
+    }
+
+    #[test]
+    fn karaoke_from_an_ass_file() {
+        let sub = load_ass(
+            "karaoke",
+            "Dialogue: 0,0:00:01.00,0:00:05.00,Default,,0,0,0,,{\\k50}One {\\kf100}twoo{\\K50}ab{\\k50}end\n",
+        );
+        let sung = |ms: u64| {
+            let shown = sub.lines_at(Duration::from_millis(ms), 100).remove(0);
+            assert_eq!(shown.text, "One twooabend");
+            shown
+                .sung
+                .iter()
+                .map(|r| shown.text[r.clone()].to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sung(1000), ["One "]);
+        assert_eq!(sung(2000), ["One tw"]);
+        // the same \k50 twice is two syllables
+        assert_eq!(sung(2750), ["One twooa"]);
+        assert_eq!(sung(3000), ["One twooabend"]);
+    }
+
+    #[test]
+    fn events_go_by_alignment_then_layer() {
+        let mut top = text("Top");
+        top.insert(0, ASSText::Override(ASSOverride::An(9)));
+        let sub = import(
+            0,
+            vec![
+                event(0, 5000, 1, text("Second")),
+                event(0, 5000, 0, text("First")),
+                event(0, 5000, 0, top),
+                event(3000, 5000, 0, text("Later")),
+            ],
+        );
+        assert_eq!(lines(&sub, 40), ["Top", "First", "Second"]);
+        let columns: Vec<u8> = sub
+            .lines_at(Duration::from_secs(2), 40)
+            .iter()
+            .map(|l| l.column)
+            .collect();
+        assert_eq!(columns, [3, 2, 2]);
+    }
+
+    #[test]
+    fn alignment_from_an_ass_file() {
+        let sub = load_ass(
+            "align",
+            "Dialogue: 0,0:00:00.00,0:00:05.00,Default,,0,0,0,,Bottom\n\
+             Dialogue: 1,0:00:00.00,0:00:05.00,Default,,0,0,0,,{\\an8}Top\n\
+             Dialogue: 0,0:00:00.00,0:00:05.00,Default,,0,0,0,,{\\an4\\i1}Left\n",
+        );
+        assert_eq!(lines(&sub, 40), ["Top", "Left", "Bottom"]);
+        let columns: Vec<u8> = sub
+            .lines_at(Duration::from_secs(2), 40)
+            .iter()
+            .map(|l| l.column)
+            .collect();
+        assert_eq!(columns, [2, 1, 2]);
+    }
+
+    #[test]
+    fn wrap_styles_and_breaks() {
+        let wrapped = |style: u8, s: &str, width: usize| {
+            lines(&import(style, vec![event(0, 5000, 0, text(s))]), width)
+        };
+        assert_eq!(wrapped(0, "aa bb cc dd ee", 10), ["aa bb cc", "dd ee"]);
+        assert_eq!(wrapped(1, "aa bb cc dd ee", 10), ["aa bb cc", "dd ee"]);
+        assert_eq!(wrapped(3, "aa bb cc dd ee", 10), ["aa bb", "cc dd ee"]);
+        assert_eq!(wrapped(0, "aaa bb ccc ddd", 11), ["aaa bb", "ccc ddd"]);
+        assert_eq!(wrapped(2, "aa bb cc dd ee", 5), ["aa bb cc dd ee"]);
+        assert_eq!(wrapped(0, "one\\Ntwo", 40), ["one", "two"]);
+        assert_eq!(wrapped(0, "one\\ntwo", 40), ["one two"]);
+        assert_eq!(wrapped(2, "one\\ntwo", 40), ["one", "two"]);
+        assert_eq!(wrapped(0, "no\\hbreak here", 8), ["no\u{a0}break", "here"]);
+        assert_eq!(wrapped(1, "abcdefgh", 3), ["abc", "def", "gh"]);
+
+        // lyrics lines, escapes are just text there
+        assert_eq!(wrap_text("aa bb cc dd ee", 10), ["aa bb cc", "dd ee"]);
+        assert_eq!(wrap_text("C:\\Nope", 40), ["C:\\Nope"]);
+        assert_eq!(wrap_text("", 10), [""]);
+    }
+}
@@ -184,6 +184,23 @@ impl<Id: PartialEq + Copy> UI<Id> {
         }
     }
 
+    /// Layout pass. Drops every allocation and every pending instruction, then takes the new size.
+    /// Regions only make sense for the size they were made for, so allocate everything again afterwards.
+    /// Used for terminal resizes, the caller is expected to redraw from scratch.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.ownership.clear();
+        self.table.inst.clear();
+        self.table.blob.clear();
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
     #[allow(dead_code)]
     pub fn get_ownership(&self) -> &Vec<UIElement<Id>> {
         &self.ownership
@@ -300,6 +317,36 @@ impl<Id: PartialEq + Copy> UI<Id> {
         self.table.si_blob(b, x, y, color);
     }
 
+    /// Like write, with parts of the text in other colors. Spans are byte ranges of text.
+    pub fn write_spans(
+        &mut self,
+        id: &Id,
+        x: usize,
+        y: usize,
+        text: &str,
+        color: ColorIntegerSize,
+        spans: &[(std::ops::Range<usize>, ColorIntegerSize)],
+    ) {
+        self.write(id, x, y, text, color);
+        let (rx, ry) = match self.find(id) {
+            Some(o) => (o.range_x, o.range_y),
+            None => return,
+        };
+        if self.idx(rx.0, y + ry.0).is_none()
+            || !blob_fit(x + rx.0, UnicodeWidthStr::width(text), rx)
+        {
+            return;
+        }
+        for (span, span_color) in spans {
+            let Some(part) = text.get(span.clone()) else {
+                continue;
+            };
+            let column = x + rx.0 + UnicodeWidthStr::width(&text[..span.start]);
+            self.table
+                .si_blob(part.as_bytes(), column, y + ry.0, *span_color);
+        }
+    }
+
 
     pub fn write_simy(&mut self, id: &Id, mut x: usize, mut y: usize, text: &str, color: ColorIntegerSize, l: usize) {
         let w = UnicodeWidthStr::width(text);
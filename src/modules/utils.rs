@@ -1,5 +1,7 @@
 use audiotags::{Album, Tag};
 
+use super::loudness::GainMode;
+
 use unicode_width::UnicodeWidthStr;
 use unicode_segmentation::UnicodeSegmentation;
 use std::time::{Duration, Instant};
@@ -28,29 +30,6 @@ impl Volume {
     }
 }
 
-pub fn artist_data(filepath: &str) -> String {
-    let tag =  Tag::new().read_from_path(filepath);
-    match tag {
-        Ok(t) => {
-            let artist = t.artists().unwrap_or(vec!["Unknown"]).join(", ");
-            artist
-        }
-        Err(_) => "Unknown".to_string(),
-    }
-}
-pub fn album_data(filepath: &str) -> String {
-    let tag = Tag::new().read_from_path(filepath);
-    match tag {
-        Ok(t) => {
-            let artist = t.album_title();
-            match artist {
-                Some(a) => a.to_string(),
-                None => "".to_string(),
-            }
-        }
-        Err(_) => "".to_string(),
-    }
-}
 pub fn addto_album(filepath: &str, new_playlist: &str) -> Result<(), String> {
     let mut tag =
         Tag::new().read_from_path(filepath).map_err(|e| format!("Failed to read ID3 tag: {}", e))?;
@@ -68,6 +47,24 @@ pub fn change_artist(filepath: &str, new_artist: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Cuts text down to width columns, marking the cut with an ellipsis.
+pub fn fit_width(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    for g in text.graphemes(true) {
+        if out.width() + g.width() + 1 > width {
+            break;
+        }
+        out.push_str(g);
+    }
+    if width > 0 {
+        out.push('…');
+    }
+    out
+}
+
 pub struct SlidingText {
     graphemes: Vec<String>,
     grapheme_width: usize,
@@ -96,6 +93,11 @@ impl SlidingText {
         !(self.grapheme_width <= self.width)
     }
 
+    pub fn set_width(&mut self, width: usize) {
+        self.width = width;
+        self.offset = 0;
+    }
+
     pub fn reset_to(&mut self, new_text: impl Into<String>) {
         let text = new_text.into() + "   ";
         self.graphemes = text.graphemes(true).map(|g| g.to_string()).collect();
@@ -140,15 +142,19 @@ impl SlidingText {
 pub struct SearchQuery {
     pub mode: u8,
     pub query: String,
+    /// Why the search query didn't parse, shown after it until it's edited.
+    pub error: Option<String>,
 }
 impl SearchQuery {
     pub fn default(&mut self) {
         self.mode = 0;
         self.query = String::from("false");
+        self.error = None;
     }
     pub fn to_mode(&mut self, mode: u8) {
         self.mode = mode;
         self.query = String::new();
+        self.error = None;
     }
 }
 #[derive(PartialEq)]
@@ -161,22 +167,64 @@ pub enum ReinitMode {
 pub struct Timer {
     pub fcalc: Duration,
     pub maxlen: Duration,
+    pub seek: Option<Duration>, // absolute target from mouse or D-Bus, waiting to be sent
+    pub seek_step: Duration,    // how far left/right jump
 }
 impl Timer {
     pub fn new() -> Self {
         Self {
             fcalc: Duration::ZERO,
             maxlen: Duration::ZERO,
+            seek: None,
+            seek_step: Duration::from_secs(5),
+        }
+    }
+    pub fn elapsed(&self) -> Duration {
+        self.maxlen.checked_sub(self.fcalc).unwrap_or_default()
+    }
+    /// Position at tenths of the track, 0..=9 -> 0%..90%
+    pub fn tenth(&self, n: u32) -> Duration {
+        self.maxlen * n / 10
+    }
+}
+/// Bookkeeping for gapless playback and crossfade.
+/// sent: preload the audio thread was last told about, ((path, gain), crossfade allowed)
+/// crossed: path the audio thread reported it moved into, waiting to be handled
+/// crossfade: fade length between tracks, zero means plain gapless
+pub struct Gapless {
+    pub sent: (Option<(String, f32)>, bool),
+    pub crossed: Option<String>,
+    pub crossfade: Duration,
+}
+impl Gapless {
+    pub fn new() -> Self {
+        Self {
+            sent: (None, false),
+            crossed: None,
+            crossfade: Duration::ZERO,
         }
     }
+    /// Audio thread drops its preload whenever a track starts, forget what was sent too.
+    pub fn forget(&mut self) {
+        self.sent = (None, false);
+    }
+    /// Cycles 0 -> 2 -> 4 ... -> 10 -> 0 seconds.
+    pub fn step_crossfade(&mut self) {
+        self.crossfade = if self.crossfade >= Duration::from_secs(10) {
+            Duration::ZERO
+        } else {
+            self.crossfade + Duration::from_secs(2)
+        };
+    }
 }
 pub struct State {
     pub spint: bool,
-    pub isloop: bool,
     pub desel: bool,
     pub mouse_support: bool,
     pub needs_update: bool,
     pub needs_dbus: bool,
+    pub seeked: bool, // a seek went out, D-Bus gets a Seeked signal with the next position
+    pub gain_mode: GainMode,
 }
 pub struct RpcState {
     pub reinit: bool,